@@ -1,6 +1,5 @@
 set -e
 
-cd app
-
-docker build -t poytr1/sentio-composer-app .
+# The image builds the server from the sources, so the context is the repository root
+docker build -f app/Dockerfile -t poytr1/sentio-composer-app .
 docker image push poytr1/sentio-composer-app
@@ -1,6 +1,6 @@
 set -e
 
-cd app/server && npm run start &
+cargo run -- serve &
 
 cd app/client && npm run serve &
 cd ..
@@ -0,0 +1,88 @@
+use move_core_types::account_address::AccountAddress;
+use move_core_types::identifier::Identifier;
+use move_core_types::language_storage::CORE_CODE_ADDRESS;
+use move_core_types::value::MoveValue;
+use move_table_extension::TableHandle;
+
+use crate::error::ComposerError;
+use crate::state_provider::StateProvider;
+
+/// Whether the struct is `0x1::aggregator::Aggregator`, whose value doesn't live in the struct
+/// but in the table item `key` of the table `handle`.
+pub fn is_aggregator(address: &AccountAddress, module: &str, name: &str) -> bool {
+    *address == CORE_CODE_ADDRESS && module == "aggregator" && name == "Aggregator"
+}
+
+/// Reads the value of the aggregator `(handle, key)` at `ledger_version`, the same way the
+/// aggregator natives read it.
+pub fn get_aggregator_value(
+    provider: &dyn StateProvider,
+    handle: &AccountAddress,
+    key: &AccountAddress,
+    ledger_version: u64,
+) -> Result<Option<u128>, ComposerError> {
+    match provider.get_table_item(&TableHandle(*handle), &key.to_vec(), ledger_version)? {
+        Some(bytes) => bcs::from_bytes::<u128>(&bytes)
+            .map(Some)
+            .map_err(|e| ComposerError::Conversion(format!("Invalid aggregator value: {}", e))),
+        None => Ok(None),
+    }
+}
+
+/// Adds the `value` field to the annotated fields of an aggregator, resolved from the chain state.
+pub fn resolve_aggregator_fields(
+    mut fields: Vec<(Identifier, MoveValue)>,
+    provider: &dyn StateProvider,
+    ledger_version: u64,
+) -> Result<Vec<(Identifier, MoveValue)>, ComposerError> {
+    let address_field = |name: &str| {
+        fields.iter().find_map(|(field, value)| match value {
+            MoveValue::Address(address) if field.as_str() == name => Some(*address),
+            _ => None,
+        })
+    };
+    if let (Some(handle), Some(key)) = (address_field("handle"), address_field("key")) {
+        if let Some(value) = get_aggregator_value(provider, &handle, &key, ledger_version)? {
+            fields.push((Identifier::new("value").unwrap(), MoveValue::U128(value)));
+        }
+    }
+    Ok(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aggregator::resolve_aggregator_fields;
+    use crate::state_provider::InMemoryStateProvider;
+    use move_core_types::account_address::AccountAddress;
+    use move_core_types::identifier::Identifier;
+    use move_core_types::value::MoveValue;
+    use move_table_extension::TableHandle;
+
+    #[test]
+    fn test_resolve_aggregator_value() {
+        let handle = AccountAddress::from_hex_literal("0x2").unwrap();
+        let key = AccountAddress::from_hex_literal("0x3").unwrap();
+        let mut state = InMemoryStateProvider::new(4, 10);
+        state.add_table_item(
+            TableHandle(handle),
+            key.to_vec(),
+            bcs::to_bytes(&42u128).unwrap(),
+        );
+        let fields = vec![
+            (
+                Identifier::new("handle").unwrap(),
+                MoveValue::Address(handle),
+            ),
+            (Identifier::new("key").unwrap(), MoveValue::Address(key)),
+            (
+                Identifier::new("limit").unwrap(),
+                MoveValue::U128(u128::MAX),
+            ),
+        ];
+        let fields = resolve_aggregator_fields(fields, &state, 10).unwrap();
+        assert_eq!(
+            fields.last().unwrap(),
+            &(Identifier::new("value").unwrap(), MoveValue::U128(42))
+        );
+    }
+}
@@ -0,0 +1,580 @@
+use aptos_sdk::rest_client::aptos_api_types::{MoveStructTag, MoveType};
+use move_core_types::account_address::AccountAddress;
+use move_core_types::identifier::Identifier;
+use move_core_types::language_storage::{ModuleId, CORE_CODE_ADDRESS};
+use move_core_types::value::{MoveStruct, MoveValue};
+use serde_json::Value;
+use std::fs;
+use std::str::FromStr;
+
+use crate::error::ComposerError;
+use crate::module_resolver::CacheModuleResolver;
+
+/// Encodes the argument `value` of the parameter type `t`. Arguments given on the command line
+/// are JSON strings, so every type can be written as a string as well: numbers as `"1"`, vectors
+/// as a JSON array `"[1, 2]"` or comma separated `"1,2"`, `vector<u8>` as hex, base64, text or a
+/// file, and structs as a JSON object `"{\"field\": 1}"`.
+///
+/// The fields of the structs are looked up in the ABI of their module at `ledger_version`. Files
+/// are only read with `read_files`, which only the command line sets.
+pub fn encode_argument(
+    value: &Value,
+    t: &MoveType,
+    module_resolver: &CacheModuleResolver,
+    ledger_version: u64,
+    read_files: bool,
+) -> Result<MoveValue, ComposerError> {
+    let value = match t {
+        MoveType::Bool => match value {
+            Value::Bool(b) => MoveValue::Bool(*b),
+            _ => MoveValue::Bool(parse_bool(value, t)?),
+        },
+        MoveType::U8 => MoveValue::U8(parse_number(value, t)?),
+        MoveType::U64 => MoveValue::U64(parse_number(value, t)?),
+        MoveType::U128 => MoveValue::U128(parse_number(value, t)?),
+        MoveType::Address => MoveValue::Address(parse_address(&scalar_to_string(value, t)?)?),
+        // Suppose it's an account parameter
+        MoveType::Signer => MoveValue::Signer(parse_address(&scalar_to_string(value, t)?)?),
+        MoveType::Vector { items } => {
+            encode_vector(value, items, module_resolver, ledger_version, read_files)?
+        }
+        MoveType::Struct(struct_tag) => encode_struct(
+            value,
+            struct_tag,
+            module_resolver,
+            ledger_version,
+            read_files,
+        )?,
+        MoveType::GenericTypeParam { index } => {
+            return Err(ComposerError::InvalidInput(format!(
+                "Generic type parameter T{} has no type argument",
+                index
+            )))
+        }
+        MoveType::Reference { .. } => {
+            return Err(ComposerError::InvalidInput(String::from(
+                "Reference type is not supported",
+            )))
+        }
+        MoveType::Unparsable(t) => {
+            return Err(ComposerError::InvalidInput(format!(
+                "Unparsable parameter type {}",
+                t
+            )))
+        }
+    };
+    Ok(value)
+}
+
+fn encode_vector(
+    value: &Value,
+    items: &MoveType,
+    module_resolver: &CacheModuleResolver,
+    ledger_version: u64,
+    read_files: bool,
+) -> Result<MoveValue, ComposerError> {
+    let elements = match value {
+        Value::Array(elements) => elements.clone(),
+        Value::String(s) => {
+            let s = s.trim();
+            if s.starts_with('[') {
+                serde_json::from_str::<Vec<Value>>(s).map_err(|e| {
+                    ComposerError::InvalidInput(format!(
+                        "Cannot parse `{}` as vector<{}>: {}",
+                        s, items, e
+                    ))
+                })?
+            } else if matches!(items, MoveType::U8) {
+                return Ok(MoveValue::vector_u8(parse_bytes(s, read_files)?));
+            } else if matches!(items, MoveType::Vector { .. }) {
+                return Err(ComposerError::InvalidInput(format!(
+                    "Expect a JSON array for vector<{}>, got `{}`",
+                    items, s
+                )));
+            } else if s.is_empty() {
+                vec![]
+            } else {
+                s.split(',')
+                    .map(|element| Value::String(element.trim().to_string()))
+                    .collect()
+            }
+        }
+        _ => {
+            return Err(ComposerError::InvalidInput(format!(
+                "Expect an array for vector<{}>, got {}",
+                items, value
+            )))
+        }
+    };
+    Ok(MoveValue::Vector(
+        elements
+            .iter()
+            .map(|element| {
+                encode_argument(element, items, module_resolver, ledger_version, read_files)
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+    ))
+}
+
+/// Encodes the framework structs from their natural value: a `String` from a string, an
+/// `Option<T>` from `null` or a `T`, an `Object<T>` from its address and a `FixedPoint32` from a
+/// decimal number. Any other struct is encoded field by field, from a JSON object keyed by the
+/// field names or a JSON array in the field order.
+fn encode_struct(
+    value: &Value,
+    struct_tag: &MoveStructTag,
+    module_resolver: &CacheModuleResolver,
+    ledger_version: u64,
+    read_files: bool,
+) -> Result<MoveValue, ComposerError> {
+    let address = AccountAddress::from_bytes(struct_tag.address.inner().into_bytes())
+        .map_err(|e| ComposerError::InvalidInput(e.to_string()))?;
+    let t = MoveType::Struct(struct_tag.clone());
+    let type_param = |index: usize| {
+        struct_tag.generic_type_params.get(index).ok_or_else(|| {
+            ComposerError::InvalidInput(format!("Missing type parameter of {}", struct_tag))
+        })
+    };
+    let framework_struct = if address == CORE_CODE_ADDRESS {
+        (struct_tag.module.as_str(), struct_tag.name.as_str())
+    } else {
+        ("", "")
+    };
+    let fields = match framework_struct {
+        ("string", "String") => {
+            let s = match value {
+                Value::String(s) => s.clone(),
+                _ => scalar_to_string(value, &t)?,
+            };
+            vec![MoveValue::vector_u8(s.into_bytes())]
+        }
+        ("option", "Option") => match value {
+            Value::Null => vec![MoveValue::Vector(vec![])],
+            Value::String(s) if s.trim() == "null" => vec![MoveValue::Vector(vec![])],
+            _ => vec![MoveValue::Vector(vec![encode_argument(
+                value,
+                type_param(0)?,
+                module_resolver,
+                ledger_version,
+                read_files,
+            )?])],
+        },
+        ("object", "Object") => vec![MoveValue::Address(parse_address(&scalar_to_string(
+            value, &t,
+        )?)?)],
+        ("fixed_point32", "FixedPoint32") => vec![MoveValue::U64(parse_fixed_point32(
+            &scalar_to_string(value, &t)?,
+        )?)],
+        _ => encode_struct_fields(
+            value,
+            address,
+            struct_tag,
+            module_resolver,
+            ledger_version,
+            read_files,
+        )?,
+    };
+    Ok(MoveValue::Struct(MoveStruct::Runtime(fields)))
+}
+
+fn encode_struct_fields(
+    value: &Value,
+    address: AccountAddress,
+    struct_tag: &MoveStructTag,
+    module_resolver: &CacheModuleResolver,
+    ledger_version: u64,
+    read_files: bool,
+) -> Result<Vec<MoveValue>, ComposerError> {
+    let module = ModuleId::new(
+        address,
+        Identifier::new(struct_tag.module.as_str())
+            .map_err(|e| ComposerError::InvalidInput(e.to_string()))?,
+    );
+    let (_, abi) = module_resolver.get_module(&module, ledger_version)?;
+    let fields = abi
+        .ok_or_else(|| ComposerError::ModuleNotFound(module.short_str_lossless()))?
+        .structs
+        .into_iter()
+        .find(|s| s.name.to_string() == struct_tag.name.to_string())
+        .ok_or_else(|| ComposerError::InvalidInput(format!("Struct {} is not found", struct_tag)))?
+        .fields;
+    let value = match value {
+        Value::String(s) if s.trim().starts_with('{') || s.trim().starts_with('[') => {
+            serde_json::from_str::<Value>(s).map_err(|e| {
+                ComposerError::InvalidInput(format!(
+                    "Cannot parse `{}` as {}: {}",
+                    s, struct_tag, e
+                ))
+            })?
+        }
+        _ => value.clone(),
+    };
+    let values = match &value {
+        Value::Object(values) if values.len() == fields.len() => fields
+            .iter()
+            .map(|field| values.get(field.name.as_str()).cloned())
+            .collect::<Option<Vec<_>>>(),
+        Value::Array(values) if values.len() == fields.len() => Some(values.clone()),
+        _ => None,
+    };
+    let field_names = || {
+        fields
+            .iter()
+            .map(|field| field.name.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let values = values.ok_or_else(|| {
+        ComposerError::InvalidInput(format!(
+            "Expect the fields [{}] of {}, got {}",
+            field_names(),
+            struct_tag,
+            value
+        ))
+    })?;
+    fields
+        .iter()
+        .zip(values.iter())
+        .map(|(field, value)| {
+            let t = substitute_type_params(&field.typ, &struct_tag.generic_type_params)?;
+            encode_argument(value, &t, module_resolver, ledger_version, read_files)
+        })
+        .collect()
+}
+
+/// Replaces the generic type parameters in `t` with `type_params`.
+pub fn substitute_type_params(
+    t: &MoveType,
+    type_params: &[MoveType],
+) -> Result<MoveType, ComposerError> {
+    let t = match t {
+        MoveType::GenericTypeParam { index } => {
+            type_params.get(*index as usize).cloned().ok_or_else(|| {
+                ComposerError::InvalidInput(format!("Missing type parameter T{}", index))
+            })?
+        }
+        MoveType::Vector { items } => MoveType::Vector {
+            items: Box::new(substitute_type_params(items, type_params)?),
+        },
+        MoveType::Reference { mutable, to } => MoveType::Reference {
+            mutable: *mutable,
+            to: Box::new(substitute_type_params(to, type_params)?),
+        },
+        MoveType::Struct(struct_tag) => MoveType::Struct(MoveStructTag {
+            generic_type_params: struct_tag
+                .generic_type_params
+                .iter()
+                .map(|t| substitute_type_params(t, type_params))
+                .collect::<Result<Vec<_>, _>>()?,
+            ..struct_tag.clone()
+        }),
+        _ => t.clone(),
+    };
+    Ok(t)
+}
+
+/// Parses a decimal number into the raw value of a `FixedPoint32`, the number times 2^32.
+/// Digits beyond the precision of 32 fractional bits are truncated.
+fn parse_fixed_point32(arg: &str) -> Result<u64, ComposerError> {
+    let invalid = || ComposerError::InvalidInput(format!("Cannot parse `{}` as FixedPoint32", arg));
+    let (integer, fraction) = arg.split_once('.').unwrap_or((arg, ""));
+    if !fraction.chars().all(|c| c.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let integer = integer.parse::<u128>().map_err(|_| invalid())?;
+    if integer > u32::MAX as u128 {
+        return Err(invalid());
+    }
+    // A multiple of 2^-32 has at most 32 decimal digits, so the first 32 digits round down the
+    // same as the whole fraction, and the fraction times 2^32 is these digits over 5^32
+    let digits = fraction
+        .chars()
+        .chain(std::iter::repeat('0'))
+        .take(32)
+        .collect::<String>();
+    let fraction = digits.parse::<u128>().map_err(|_| invalid())? / 5u128.pow(32);
+    Ok(((integer << 32) + fraction) as u64)
+}
+
+/// Decodes a `vector<u8>` written as `hex:<HEX>` or `0x<HEX>`, `base64:<BASE64>`, `utf8:<TEXT>`
+/// or `@<FILE>` for the content of a file, if `read_files` allows it. Any other string stands for
+/// its UTF-8 bytes.
+fn parse_bytes(arg: &str, read_files: bool) -> Result<Vec<u8>, ComposerError> {
+    let invalid = |encoding: &str, e: String| {
+        ComposerError::InvalidInput(format!("Cannot decode `{}` as {}: {}", arg, encoding, e))
+    };
+    if let Some(s) = arg.strip_prefix("hex:") {
+        hex::decode(s.strip_prefix("0x").unwrap_or(s)).map_err(|e| invalid("hex", e.to_string()))
+    } else if let Some(s) = arg.strip_prefix("0x") {
+        hex::decode(s).map_err(|e| invalid("hex", e.to_string()))
+    } else if let Some(s) = arg.strip_prefix("base64:") {
+        base64::decode(s).map_err(|e| invalid("base64", e.to_string()))
+    } else if let Some(s) = arg.strip_prefix("utf8:") {
+        Ok(s.as_bytes().to_vec())
+    } else if let Some(path) = arg.strip_prefix('@') {
+        if !read_files {
+            return Err(invalid(
+                "file",
+                String::from("files are only read on the command line"),
+            ));
+        }
+        fs::read(path).map_err(|e| invalid("file", e.to_string()))
+    } else {
+        Ok(arg.as_bytes().to_vec())
+    }
+}
+
+fn scalar_to_string(value: &Value, t: &MoveType) -> Result<String, ComposerError> {
+    match value {
+        Value::String(s) => Ok(s.trim().to_string()),
+        Value::Number(n) => Ok(n.to_string()),
+        Value::Bool(b) => Ok(b.to_string()),
+        _ => Err(ComposerError::InvalidInput(format!(
+            "Cannot parse {} as {}",
+            value, t
+        ))),
+    }
+}
+
+fn parse_bool(value: &Value, t: &MoveType) -> Result<bool, ComposerError> {
+    match scalar_to_string(value, t)?.as_str() {
+        "true" | "t" | "1" => Ok(true),
+        "false" | "f" | "0" => Ok(false),
+        arg => Err(ComposerError::InvalidInput(format!(
+            "Cannot parse `{}` as {}",
+            arg, t
+        ))),
+    }
+}
+
+fn parse_number<T: FromStr>(value: &Value, t: &MoveType) -> Result<T, ComposerError> {
+    let arg = scalar_to_string(value, t)?;
+    arg.parse::<T>()
+        .map_err(|_| ComposerError::InvalidInput(format!("Cannot parse `{}` as {}", arg, t)))
+}
+
+fn parse_address(arg: &str) -> Result<AccountAddress, ComposerError> {
+    AccountAddress::from_hex_literal(arg)
+        .map_err(|_| ComposerError::InvalidInput(format!("Cannot parse `{}` as address", arg)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arguments::{encode_argument, parse_fixed_point32, substitute_type_params};
+    use crate::error::ComposerError;
+    use crate::module_resolver::CacheModuleResolver;
+    use crate::state_provider::InMemoryStateProvider;
+    use crate::types::Network;
+    use aptos_sdk::rest_client::aptos_api_types::{MoveStructTag, MoveType};
+    use move_core_types::language_storage::CORE_CODE_ADDRESS;
+    use move_core_types::value::{MoveStruct, MoveValue};
+    use serde_json::{json, Value};
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use uuid::Uuid;
+
+    fn encode(value: &Value, t: &MoveType) -> Result<MoveValue, ComposerError> {
+        let provider = Arc::new(InMemoryStateProvider::new(4, 10));
+        let module_resolver = CacheModuleResolver::new(Network::Localnet, provider, None, false);
+        encode_argument(value, t, &module_resolver, 10, true)
+    }
+
+    fn vector(items: MoveType) -> MoveType {
+        MoveType::Vector {
+            items: Box::new(items),
+        }
+    }
+
+    fn framework_struct(t: &str) -> MoveType {
+        MoveType::Struct(MoveStructTag::from_str(t).unwrap())
+    }
+
+    #[test]
+    fn test_encode_vectors() {
+        let expected = MoveValue::Vector(vec![MoveValue::U64(1), MoveValue::U64(2)]);
+        for value in [json!("1,2"), json!(" [1, \"2\"] "), json!([1, "2"])] {
+            assert_eq!(encode(&value, &vector(MoveType::U64)).unwrap(), expected);
+        }
+        assert_eq!(
+            encode(&json!(""), &vector(MoveType::Bool)).unwrap(),
+            MoveValue::Vector(vec![])
+        );
+        assert_eq!(
+            encode(&json!("0x1,true"), &vector(MoveType::Address))
+                .unwrap_err()
+                .code(),
+            "INVALID_INPUT"
+        );
+        assert_eq!(
+            encode(&json!("abc"), &vector(MoveType::U8)).unwrap(),
+            MoveValue::vector_u8(b"abc".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_encode_bools() {
+        for (value, b) in [
+            (json!(true), true),
+            (json!("true"), true),
+            (json!(" false "), false),
+            (json!("t"), true),
+            (json!("1"), true),
+            (json!(1), true),
+            (json!("f"), false),
+            (json!("0"), false),
+        ] {
+            assert_eq!(encode(&value, &MoveType::Bool).unwrap(), MoveValue::Bool(b));
+        }
+        for value in [
+            json!("ture"),
+            json!("yes"),
+            json!(2),
+            json!("TRUE"),
+            json!(""),
+        ] {
+            assert_eq!(
+                encode(&value, &MoveType::Bool).unwrap_err().code(),
+                "INVALID_INPUT"
+            );
+        }
+    }
+
+    #[test]
+    fn test_encode_bytes() {
+        let path = std::env::temp_dir().join(format!("bytes_{}", Uuid::new_v4()));
+        std::fs::write(&path, [1, 2, 3]).unwrap();
+        let file = format!("@{}", path.display());
+        for (arg, bytes) in [
+            ("0x010203", vec![1, 2, 3]),
+            ("hex:010203", vec![1, 2, 3]),
+            ("hex:0x010203", vec![1, 2, 3]),
+            ("base64:AQID", vec![1, 2, 3]),
+            ("utf8:0x01", b"0x01".to_vec()),
+            ("abc", b"abc".to_vec()),
+            ("[1, 2, 3]", vec![1, 2, 3]),
+            ("0x", vec![]),
+            (file.as_str(), vec![1, 2, 3]),
+        ] {
+            assert_eq!(
+                encode(&json!(arg), &vector(MoveType::U8)).unwrap(),
+                MoveValue::vector_u8(bytes)
+            );
+        }
+        // Only the command line reads files
+        let provider = Arc::new(InMemoryStateProvider::new(4, 10));
+        let module_resolver = CacheModuleResolver::new(Network::Localnet, provider, None, false);
+        assert_eq!(
+            encode_argument(
+                &json!(file),
+                &vector(MoveType::U8),
+                &module_resolver,
+                10,
+                false
+            )
+            .unwrap_err()
+            .code(),
+            "INVALID_INPUT"
+        );
+        assert!(encode(&json!("0x123"), &vector(MoveType::U8)).is_err());
+        assert!(encode(&json!("base64:!"), &vector(MoveType::U8)).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_encode_nested_vectors() {
+        let t = vector(vector(MoveType::Address));
+        assert_eq!(
+            encode(&json!("[[\"0x1\"], []]"), &t).unwrap(),
+            MoveValue::Vector(vec![
+                MoveValue::Vector(vec![MoveValue::Address(CORE_CODE_ADDRESS)]),
+                MoveValue::Vector(vec![]),
+            ])
+        );
+        assert!(encode(&json!("0x1,0x2"), &t).is_err());
+    }
+
+    #[test]
+    fn test_encode_framework_structs() {
+        let wrap = |fields| MoveValue::Struct(MoveStruct::Runtime(fields));
+        assert_eq!(
+            encode(&json!("abc"), &framework_struct("0x1::string::String")).unwrap(),
+            wrap(vec![MoveValue::vector_u8(b"abc".to_vec())])
+        );
+        let option = framework_struct("0x1::option::Option<u64>");
+        assert_eq!(
+            encode(&json!("null"), &option).unwrap(),
+            wrap(vec![MoveValue::Vector(vec![])])
+        );
+        assert_eq!(
+            encode(&json!(5), &option).unwrap(),
+            wrap(vec![MoveValue::Vector(vec![MoveValue::U64(5)])])
+        );
+        assert_eq!(
+            encode(
+                &json!("0x1"),
+                &framework_struct("0x1::object::Object<0x1::object::ObjectCore>")
+            )
+            .unwrap(),
+            wrap(vec![MoveValue::Address(CORE_CODE_ADDRESS)])
+        );
+        assert_eq!(
+            encode(
+                &json!("1.5"),
+                &framework_struct("0x1::fixed_point32::FixedPoint32")
+            )
+            .unwrap(),
+            wrap(vec![MoveValue::U64(3 << 31)])
+        );
+    }
+
+    #[test]
+    fn test_parse_fixed_point32() {
+        assert_eq!(parse_fixed_point32("2").unwrap(), 2 << 32);
+        assert_eq!(parse_fixed_point32("0.25").unwrap(), 1 << 30);
+        assert_eq!(parse_fixed_point32("1.").unwrap(), 1 << 32);
+
+        // The extra digits are truncated, 2^-32 being the smallest step
+        assert_eq!(
+            parse_fixed_point32("0.250000000000000000").unwrap(),
+            1 << 30
+        );
+        assert_eq!(
+            parse_fixed_point32("0.2500000000000000000000000000000000000001").unwrap(),
+            1 << 30
+        );
+        assert_eq!(
+            parse_fixed_point32("0.00000000023283064365386962890625").unwrap(),
+            1
+        );
+        assert_eq!(
+            parse_fixed_point32("0.000000000232830643653869628906249999").unwrap(),
+            0
+        );
+        assert_eq!(
+            parse_fixed_point32("4294967295.99999999999999999999999").unwrap(),
+            u64::MAX
+        );
+        assert!(parse_fixed_point32("4294967296").is_err());
+        assert!(parse_fixed_point32("-1").is_err());
+        assert!(parse_fixed_point32("1.2.3").is_err());
+    }
+
+    #[test]
+    fn test_substitute_type_params() {
+        let t = |index| MoveType::GenericTypeParam { index };
+        let type_params = vec![MoveType::U64, framework_struct("0x1::string::String")];
+        assert_eq!(
+            substitute_type_params(&vector(t(1)), &type_params).unwrap(),
+            vector(framework_struct("0x1::string::String"))
+        );
+        let option = MoveType::Struct(MoveStructTag {
+            generic_type_params: vec![t(0)],
+            ..MoveStructTag::from_str("0x1::option::Option<u8>").unwrap()
+        });
+        assert_eq!(
+            substitute_type_params(&option, &type_params).unwrap(),
+            framework_struct("0x1::option::Option<u64>")
+        );
+        assert!(substitute_type_params(&t(2), &type_params).is_err());
+    }
+}
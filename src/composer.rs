@@ -0,0 +1,888 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Instant;
+
+use aptos_gas::{AptosGasMeter, StorageGasParameters};
+use aptos_sdk::rest_client::aptos_api_types::MoveType;
+use aptos_vm::natives;
+use framework::natives::aggregator_natives::NativeAggregatorContext;
+use framework::natives::code::NativeCodeContext;
+use framework::natives::cryptography::ristretto255_point::NativeRistrettoPointContext;
+use framework::natives::transaction_context::NativeTransactionContext;
+use log::warn;
+use move_core_types::account_address::AccountAddress;
+use move_core_types::identifier::{IdentStr, Identifier};
+use move_core_types::language_storage::{ModuleId, StructTag, TypeTag, CORE_CODE_ADDRESS};
+use move_core_types::value::MoveValue;
+use move_core_types::vm_status::StatusCode;
+use move_table_extension::NativeTableContext;
+use move_vm_runtime::move_vm::MoveVM;
+use move_vm_runtime::native_extensions::NativeContextExtensions;
+use move_vm_types::gas::UnmeteredGasMeter;
+use once_cell::sync::OnceCell;
+use serde_json::Value;
+
+use crate::arguments::substitute_type_params;
+use crate::config::ToolConfig;
+use crate::converter::{annotate_value, move_value_to_json};
+use crate::error::ComposerError;
+use crate::fixture::{FixtureStore, RecordingStateProvider};
+use crate::helper::{get_network_config, resolve_ledger_version, serialize_input_params};
+use crate::module_resolver::CacheModuleResolver;
+use crate::on_chain_config::OnChainConfig;
+use crate::state_provider::{CachingStateProvider, RestStateProvider, StateProvider};
+use crate::storage::InMemoryLazyStorage;
+use crate::types::{ExecutionResult, ExecutionTimings, GasBudget, Network};
+
+/// Calls view functions on one network. The state provider and the module resolver are kept
+/// across calls, so a long-running service only pays for loading a module once.
+pub struct Composer {
+    network: Network,
+    provider: Arc<dyn StateProvider>,
+    module_resolver: CacheModuleResolver,
+    fixture: Option<FixtureStore>,
+    expected_chain_id: Option<u8>,
+    chain_id: OnceCell<u8>,
+    gas_budget: GasBudget,
+    prefetch_modules: bool,
+    read_files: bool,
+}
+
+impl Composer {
+    pub fn new(network: Network, config: &ToolConfig) -> Result<Self, ComposerError> {
+        Self::new_with_fixture(network, config, None)
+    }
+
+    /// Same as `new`, but the chain state is recorded into or replayed from `fixture`.
+    pub fn new_with_fixture(
+        network: Network,
+        config: &ToolConfig,
+        fixture: Option<FixtureStore>,
+    ) -> Result<Self, ComposerError> {
+        let provider: Arc<dyn StateProvider> = match &fixture {
+            Some(fixture) if fixture.is_replay() => Arc::new(fixture.clone()),
+            Some(fixture) => Arc::new(RecordingStateProvider::new(
+                Arc::new(RestStateProvider::from_config(&network, config)?),
+                fixture.clone(),
+            )),
+            None => Arc::new(RestStateProvider::from_config(&network, config)?),
+        };
+        // The disk cache would hide the modules from the fixture
+        let cache_folder = if fixture.is_none() {
+            config.cache_folder.clone()
+        } else {
+            None
+        };
+        let module_resolver = CacheModuleResolver::new(
+            network.clone(),
+            provider.clone(),
+            cache_folder,
+            config.enable_module_caching,
+        );
+        Ok(Self {
+            expected_chain_id: get_network_config(&network, config)
+                .ok()
+                .and_then(|network_config| network_config.chain_id),
+            network,
+            provider,
+            module_resolver,
+            fixture,
+            chain_id: OnceCell::new(),
+            gas_budget: GasBudget::default(),
+            prefetch_modules: false,
+            read_files: false,
+        })
+    }
+
+    /// Runs the calls against any source of chain state. The module disk cache is not used, and
+    /// the chain id is only verified against `expected_chain_id` when it's given.
+    pub fn with_state_provider(
+        network: Network,
+        provider: Arc<dyn StateProvider>,
+        expected_chain_id: Option<u8>,
+    ) -> Self {
+        let module_resolver =
+            CacheModuleResolver::new(network.clone(), provider.clone(), None, false);
+        Self {
+            network,
+            provider,
+            module_resolver,
+            fixture: None,
+            expected_chain_id,
+            chain_id: OnceCell::new(),
+            gas_budget: GasBudget::default(),
+            prefetch_modules: false,
+            read_files: false,
+        }
+    }
+
+    pub fn network(&self) -> &Network {
+        &self.network
+    }
+
+    /// Sets the gas budget of the following calls.
+    pub fn set_gas_budget(&mut self, gas_budget: GasBudget) {
+        self.gas_budget = gas_budget;
+    }
+
+    /// Whether the following calls download all the modules under the address of the function
+    /// at once, before executing.
+    pub fn set_prefetch_modules(&mut self, prefetch_modules: bool) {
+        self.prefetch_modules = prefetch_modules;
+    }
+
+    /// Whether the `vector<u8>` arguments of the following calls can be read from a file with
+    /// `@<FILE>`. Only the command line should allow it, a server would expose its files.
+    pub fn set_read_files(&mut self, read_files: bool) {
+        self.read_files = read_files;
+    }
+
+    /// Reads the current ledger version of the state provider.
+    pub fn latest_ledger_version(&self) -> Result<u64, ComposerError> {
+        resolve_ledger_version(self.provider.as_ref(), 0)
+    }
+
+    /// Reads the chain id of the state, refusing to run on a chain other than the one configured
+    /// for the network. The check is done once per composer.
+    pub fn chain_id(&self) -> Result<u8, ComposerError> {
+        self.chain_id
+            .get_or_try_init(|| {
+                let chain_id = self.provider.chain_id()?;
+                match self.expected_chain_id {
+                    Some(expected) if expected != chain_id => Err(ComposerError::ChainIdMismatch {
+                        network: self.network.to_string(),
+                        expected,
+                        actual: chain_id,
+                    }),
+                    _ => Ok(chain_id),
+                }
+            })
+            .copied()
+    }
+
+    /// Calls the view function `function_id` (`<ADDRESS>::<MODULE_ID>::<FUNCTION_NAME>`) at
+    /// `ledger_version`, `0` meaning the latest version.
+    pub fn call_view(
+        &self,
+        function_id: &str,
+        type_args: Vec<String>,
+        args: Vec<String>,
+        ledger_version: u64,
+    ) -> Result<ExecutionResult, ComposerError> {
+        self.call_view_json(
+            function_id,
+            type_args,
+            args.into_iter().map(Value::String).collect(),
+            ledger_version,
+        )
+    }
+
+    /// Same as `call_view`, with the arguments as JSON values encoded after the parameter types,
+    /// e.g. numbers, arrays, or objects for the structs. Strings are parsed as in `call_view`.
+    pub fn call_view_json(
+        &self,
+        function_id: &str,
+        type_args: Vec<String>,
+        args: Vec<Value>,
+        ledger_version: u64,
+    ) -> Result<ExecutionResult, ComposerError> {
+        let res = self.execute_view(function_id, type_args, args, ledger_version);
+        // A failed call is recorded as well, to replay the failure
+        match &self.fixture {
+            Some(fixture) => {
+                let saved = fixture.save();
+                res.and_then(|res| saved.map(|_| res))
+            }
+            None => res,
+        }
+    }
+
+    fn execute_view(
+        &self,
+        function_id: &str,
+        type_args: Vec<String>,
+        args: Vec<Value>,
+        ledger_version: u64,
+    ) -> Result<ExecutionResult, ComposerError> {
+        let start = Instant::now();
+        let (module, func_id) = parse_function_id(function_id)?;
+
+        let is_latest = ledger_version == 0;
+        let ledger_version = resolve_ledger_version(self.provider.as_ref(), ledger_version)?;
+        if is_latest {
+            self.module_resolver.set_latest_version(ledger_version);
+        }
+        // Checked after the ledger version, whose ledger info read gives the chain id as well
+        let chain_id = self.chain_id()?;
+        let mut timings = ExecutionTimings::default();
+        // Resources are read once per execution, however many times the function touches them
+        let provider: Arc<dyn StateProvider> =
+            Arc::new(CachingStateProvider::new(self.provider.clone()));
+
+        let resource_fetch_start = Instant::now();
+        let block_timestamp_usecs = get_block_timestamp(provider.as_ref(), ledger_version);
+        let on_chain_config = OnChainConfig::load(provider.as_ref(), ledger_version)?;
+        timings.resource_fetch_us += elapsed_us(resource_fetch_start);
+
+        let module_fetch_start = Instant::now();
+        if self.prefetch_modules {
+            self.module_resolver
+                .prefetch_modules(module.address(), ledger_version)?;
+        }
+        let module_res = self.module_resolver.get_module(&module, ledger_version)?;
+        timings.module_fetch_us += elapsed_us(module_fetch_start);
+        let abi = match module_res {
+            (Some(_), Some(abi)) => abi,
+            _ => return Err(ComposerError::ModuleNotFound(module.short_str_lossless())),
+        };
+        let matched_func = abi
+            .exposed_functions
+            .into_iter()
+            .find(|f| f.name.to_string() == func_id.to_string());
+
+        let (generic_type_params, param_types, ret_types) = if let Some(f) = matched_func {
+            (f.generic_type_params, f.params, f.return_)
+        } else {
+            return Err(ComposerError::FunctionNotFound(function_id.to_string()));
+        };
+        if type_args.len() != generic_type_params.len() {
+            return Err(ComposerError::InvalidInput(format!(
+                "Expect {} type arguments for {}, got {}",
+                generic_type_params.len(),
+                function_id,
+                type_args.len()
+            )));
+        }
+        // The generic parameters and return values take the concrete types of the type arguments
+        let type_params = type_args
+            .iter()
+            .map(|tp| {
+                MoveType::from_str(tp.as_str()).map_err(|e| {
+                    ComposerError::InvalidInput(format!("Invalid type argument {}: {}", tp, e))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let ret_types = ret_types
+            .iter()
+            .map(|t| substitute_type_params(t, &type_params))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let ser_args: Vec<Vec<u8>> = serialize_input_params(
+            Some(args),
+            param_types,
+            &type_params,
+            &self.module_resolver,
+            ledger_version,
+            self.read_files,
+        )?;
+
+        let type_args: Vec<TypeTag> = type_args
+            .into_iter()
+            .map(|tp| {
+                TypeTag::from_str(tp.as_str()).map_err(|e| {
+                    ComposerError::InvalidInput(format!("Invalid type argument {}: {}", tp, e))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let storage = InMemoryLazyStorage::new(
+            ledger_version,
+            provider.clone(),
+            self.module_resolver.clone(),
+        );
+        let vm_execution_start = Instant::now();
+        let (vals, gas_used) = exec_func_internal(
+            &storage,
+            module,
+            func_id.as_ident_str(),
+            type_args,
+            ser_args,
+            chain_id,
+            on_chain_config,
+            self.gas_budget,
+        )?;
+        let vm_execution_us = elapsed_us(vm_execution_start);
+        let storage_timings = storage.timings();
+        timings.module_fetch_us += storage_timings.module_fetch_us;
+        timings.resource_fetch_us += storage_timings.resource_fetch_us;
+        timings.table_fetch_us += storage_timings.table_fetch_us;
+        timings.vm_execution_us = vm_execution_us.saturating_sub(
+            storage_timings.module_fetch_us
+                + storage_timings.resource_fetch_us
+                + storage_timings.table_fetch_us,
+        );
+
+        let conversion_start = Instant::now();
+        if vals.len() != ret_types.len() {
+            return Err(ComposerError::Conversion(format!(
+                "Expect {} return values, got {}",
+                ret_types.len(),
+                vals.len()
+            )));
+        }
+        let mut json_ret_vals = vec![];
+        for (val, t) in vals.into_iter().zip(ret_types.iter()) {
+            let val = annotate_value(
+                val,
+                t,
+                &self.module_resolver,
+                provider.as_ref(),
+                ledger_version,
+            )?;
+            json_ret_vals.push(move_value_to_json(val));
+        }
+        timings.conversion_us = elapsed_us(conversion_start);
+        timings.total_us = elapsed_us(start);
+        Ok(ExecutionResult {
+            ledger_version,
+            chain_id: Some(chain_id),
+            network: self.network.to_string(),
+            block_timestamp_usecs,
+            return_values: json_ret_vals,
+            gas_used,
+            timings,
+            ..Default::default()
+        })
+    }
+}
+
+/// Reads the block timestamp at `ledger_version` from `0x1::timestamp::CurrentTimeMicroseconds`.
+/// It's only informative, so a failure leaves it out instead of failing the call.
+fn get_block_timestamp(provider: &dyn StateProvider, ledger_version: u64) -> Option<u64> {
+    let tag = StructTag {
+        address: CORE_CODE_ADDRESS,
+        module: Identifier::new("timestamp").unwrap(),
+        name: Identifier::new("CurrentTimeMicroseconds").unwrap(),
+        type_params: vec![],
+    };
+    match provider.get_resource(&CORE_CODE_ADDRESS, &tag, ledger_version) {
+        Ok(Some(bytes)) => bcs::from_bytes::<u64>(&bytes).ok(),
+        Ok(None) => None,
+        Err(e) => {
+            warn!("Failed to read the block timestamp: {}", e);
+            None
+        }
+    }
+}
+
+fn elapsed_us(start: Instant) -> u64 {
+    start.elapsed().as_micros() as u64
+}
+
+fn parse_function_id(func: &str) -> Result<(ModuleId, Identifier), ComposerError> {
+    let invalid_function_id = || {
+        ComposerError::InvalidInput(format!(
+            "Invalid function id {}, expect `<ADDRESS>::<MODULE_ID>::<FUNCTION_NAME>`",
+            func
+        ))
+    };
+    let splitted_func: Vec<&str> = func.split("::").collect();
+    if splitted_func.len() != 3 {
+        return Err(invalid_function_id());
+    }
+    let account =
+        AccountAddress::from_hex_literal(splitted_func[0]).map_err(|_| invalid_function_id())?;
+    let module = ModuleId::new(
+        account,
+        Identifier::new(splitted_func[1]).map_err(|_| invalid_function_id())?,
+    );
+    let func_id = Identifier::new(splitted_func[2]).map_err(|_| invalid_function_id())?;
+    Ok((module, func_id))
+}
+
+/// Native extensions as a transaction session registers them. A view has no transaction, so the
+/// transaction hash and the script hash are left empty. Events don't need an extension, the VM
+/// keeps the emitted events itself and they're dropped with the session.
+fn new_extensions(storage: &InMemoryLazyStorage, chain_id: u8) -> NativeContextExtensions {
+    let txn_hash = [0u8; 32];
+    let mut extensions = NativeContextExtensions::default();
+    extensions.add(NativeTableContext::new(txn_hash, storage));
+    extensions.add(NativeAggregatorContext::new(txn_hash, storage));
+    extensions.add(NativeTransactionContext::new(vec![], chain_id));
+    extensions.add(NativeCodeContext::default());
+    extensions.add(NativeRistrettoPointContext::new());
+    extensions
+}
+
+#[allow(clippy::too_many_arguments)]
+fn exec_func_internal(
+    storage: &InMemoryLazyStorage,
+    module: ModuleId,
+    function: &IdentStr,
+    type_args: Vec<TypeTag>,
+    args: Vec<Vec<u8>>,
+    chain_id: u8,
+    on_chain_config: OnChainConfig,
+    gas_budget: GasBudget,
+) -> Result<(Vec<MoveValue>, Option<u64>), ComposerError> {
+    let gas_feature_version = on_chain_config.gas_feature_version;
+    let gas_params = on_chain_config.gas_params;
+    let natives = natives::aptos_natives(
+        gas_params.natives.clone(),
+        gas_params.misc.abs_val.clone(),
+        gas_feature_version,
+    );
+
+    let vm = MoveVM::new(natives)?;
+
+    let mut session = vm.new_session_with_extensions(storage, new_extensions(storage, chain_id));
+    let gas_budget = match gas_budget {
+        GasBudget::Unmetered => None,
+        GasBudget::Limit(gas_budget) => Some(gas_budget),
+        GasBudget::Default => Some(u64::from(gas_params.txn.maximum_number_of_gas_units)),
+    };
+    let (res, gas_used) = if let Some(gas_budget) = gas_budget {
+        // Storage is not charged, a view doesn't write anything
+        let mut gas_meter = AptosGasMeter::new(
+            gas_feature_version,
+            gas_params,
+            StorageGasParameters::free_and_unlimited(),
+            gas_budget,
+        );
+        let res = session.execute_function_bypass_visibility(
+            &module,
+            function,
+            type_args,
+            args,
+            &mut gas_meter,
+        );
+        let balance: u64 = gas_meter.balance().into();
+        (res, Some(gas_budget - balance))
+    } else {
+        let res = session.execute_function_bypass_visibility(
+            &module,
+            function,
+            type_args,
+            args,
+            &mut UnmeteredGasMeter,
+        );
+        (res, None)
+    };
+    match res {
+        Ok(success_result) => {
+            let vals = success_result
+                .return_values
+                .into_iter()
+                .map(|v| {
+                    MoveValue::simple_deserialize(&*v.0, &v.1)
+                        .map_err(|e| ComposerError::Conversion(e.to_string()))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok((vals, gas_used))
+        }
+        Err(err) if err.major_status() == StatusCode::OUT_OF_GAS => Err(ComposerError::OutOfGas {
+            gas_budget: gas_budget.unwrap_or_default(),
+        }),
+        // A storage failure is only reported as a generic error by the VM, prefer the original one
+        Err(err) => Err(storage.take_error().unwrap_or_else(|| err.into())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::composer::{parse_function_id, Composer};
+    use crate::config::{ConfigData, ToolConfig};
+    use crate::error::ComposerError;
+    use crate::fixture::{FixtureStore, RecordingStateProvider};
+    use crate::logger::CaptureLogger;
+    use crate::state_provider::InMemoryStateProvider;
+    use crate::test_utils::{module_bytecode, TestModule};
+    use crate::types::Network;
+    use log::{debug, LevelFilter};
+    use move_binary_format::file_format::{
+        Ability, AbilitySet, Bytecode, SignatureToken, StructHandleIndex,
+    };
+    use move_core_types::account_address::AccountAddress;
+    use move_core_types::identifier::Identifier;
+    use move_core_types::language_storage::{ModuleId, StructTag, CORE_CODE_ADDRESS};
+    use move_table_extension::TableHandle;
+    use once_cell::sync::Lazy;
+    use serde_json::json;
+    use simplelog::{Config, SimpleLogger};
+    use std::sync::Arc;
+    use uuid::Uuid;
+
+    static CONFIG: Lazy<ToolConfig> = Lazy::new(|| ConfigData::default().config);
+
+    #[cfg(test)]
+    #[ctor::ctor]
+    fn init() {
+        CaptureLogger::init(
+            Some(SimpleLogger::new(LevelFilter::Debug, Config::default())),
+            LevelFilter::Debug,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_parse_function_id() {
+        let (module, func_id) = parse_function_id("0x1::block::get_current_block_height").unwrap();
+        assert_eq!(module.name().as_str(), "block");
+        assert_eq!(func_id.as_str(), "get_current_block_height");
+
+        let err = parse_function_id("0x1::block").unwrap_err();
+        assert_eq!(err.code(), "INVALID_INPUT");
+        let err = parse_function_id("not_an_address::block::get_current_block_height").unwrap_err();
+        assert_eq!(err.code(), "INVALID_INPUT");
+    }
+
+    fn module_id(name: &str) -> ModuleId {
+        ModuleId::new(CORE_CODE_ADDRESS, Identifier::new(name).unwrap())
+    }
+
+    fn struct_tag(module: &str, name: &str) -> StructTag {
+        StructTag {
+            address: CORE_CODE_ADDRESS,
+            module: Identifier::new(module).unwrap(),
+            name: Identifier::new(name).unwrap(),
+            type_params: vec![],
+        }
+    }
+
+    // `0x1::block::get_current_block_height` reading the height 7 from `BlockResource`, with the
+    // timestamp of the block
+    fn block_state(chain_id: u8) -> InMemoryStateProvider {
+        let mut block = TestModule::new("block");
+        let resource = block.add_struct(
+            "BlockResource",
+            AbilitySet::EMPTY | Ability::Key,
+            vec![("height", SignatureToken::U64)],
+        );
+        let height = block.field_handle(resource, 0);
+        let framework = block.add_address_constant(CORE_CODE_ADDRESS);
+        block.add_function(
+            "get_current_block_height",
+            vec![],
+            (vec![], vec![SignatureToken::U64]),
+            Some((
+                vec![],
+                vec![
+                    Bytecode::LdConst(framework),
+                    Bytecode::ImmBorrowGlobal(resource),
+                    Bytecode::ImmBorrowField(height),
+                    Bytecode::ReadRef,
+                    Bytecode::Ret,
+                ],
+            )),
+        );
+        let mut state = InMemoryStateProvider::new(chain_id, 10);
+        state.add_module(module_id("block"), block.bytecode());
+        state.add_resource(
+            CORE_CODE_ADDRESS,
+            struct_tag("block", "BlockResource"),
+            bcs::to_bytes(&7u64).unwrap(),
+        );
+        state.add_resource(
+            CORE_CODE_ADDRESS,
+            struct_tag("timestamp", "CurrentTimeMicroseconds"),
+            bcs::to_bytes(&1668749045373513u64).unwrap(),
+        );
+        state
+    }
+
+    // A module calling the native `name` of the module `module` through the public function
+    // `call`, as the natives are only reached from Move code
+    fn native_module(
+        module: &str,
+        name: &str,
+        params: Vec<SignatureToken>,
+        returns: Vec<SignatureToken>,
+    ) -> Vec<u8> {
+        let mut native_module = TestModule::new(module);
+        let native =
+            native_module.add_function(name, vec![], (params.clone(), returns.clone()), None);
+        let mut code = (0..params.len() as u8)
+            .map(Bytecode::MoveLoc)
+            .collect::<Vec<_>>();
+        code.extend([Bytecode::Call(native), Bytecode::Ret]);
+        native_module.add_function("call", vec![], (params, returns), Some((vec![], code)));
+        native_module.bytecode()
+    }
+
+    #[test]
+    fn test_call_function_with_arguments() {
+        let mut vault = TestModule::new("vault");
+        vault.add_function(
+            "account_collateral_and_debt",
+            vec![AbilitySet::EMPTY],
+            (
+                vec![SignatureToken::Address],
+                vec![SignatureToken::Address, SignatureToken::U64],
+            ),
+            Some((
+                vec![],
+                vec![Bytecode::MoveLoc(0), Bytecode::LdU64(2), Bytecode::Ret],
+            )),
+        );
+        let mut state = InMemoryStateProvider::new(2, 10);
+        state.add_module(module_id("vault"), vault.bytecode());
+        let composer = Composer::with_state_provider(Network::Testnet, Arc::new(state), Some(2));
+
+        let account = "0xf485fdf431d489c7bd0b83efa2413a6701fe4985d3e64a299a1a2e9fb46bcb82";
+        let execution_result = composer
+            .call_view(
+                "0x1::vault::account_collateral_and_debt",
+                vec![String::from("u64")],
+                vec![String::from(account)],
+                0,
+            )
+            .unwrap();
+        assert_eq!(
+            execution_result.return_values,
+            vec![
+                json!(AccountAddress::from_hex_literal(account).unwrap()),
+                json!("2")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_get_current_block_height() {
+        let composer =
+            Composer::with_state_provider(Network::Mainnet, Arc::new(block_state(1)), Some(1));
+        let execution_result = composer
+            .call_view("0x1::block::get_current_block_height", vec![], vec![], 0)
+            .unwrap();
+        assert_eq!(execution_result.return_values, vec![json!("7")]);
+        assert_eq!(execution_result.ledger_version, 10);
+        assert_eq!(execution_result.chain_id, Some(1));
+        assert_eq!(execution_result.network, "mainnet");
+        assert_eq!(
+            execution_result.block_timestamp_usecs,
+            Some(1668749045373513)
+        );
+    }
+
+    #[test]
+    fn test_aptos_native_function() {
+        let mut state = InMemoryStateProvider::new(4, 10);
+        state.add_module(
+            module_id("aptos_hash"),
+            native_module(
+                "aptos_hash",
+                "sip_hash",
+                vec![SignatureToken::Vector(Box::new(SignatureToken::U8))],
+                vec![SignatureToken::U64],
+            ),
+        );
+        let composer = Composer::with_state_provider(Network::Localnet, Arc::new(state), None);
+        let execution_result = composer
+            .call_view(
+                "0x1::aptos_hash::call",
+                vec![],
+                vec![String::from("utf8:abc")],
+                0,
+            )
+            .unwrap();
+        assert_eq!(
+            execution_result.return_values,
+            vec![json!("4596069200710135518")]
+        );
+    }
+
+    #[test]
+    fn test_historical_ledger_version() {
+        let mut state = InMemoryStateProvider::new(2, 400000000);
+        state.add_module(
+            module_id("stability_pool"),
+            module_bytecode(
+                "stability_pool",
+                vec![(
+                    "account_deposit",
+                    vec![SignatureToken::Address],
+                    vec![SignatureToken::U64],
+                    vec![Bytecode::LdU64(0), Bytecode::Ret],
+                )],
+            ),
+        );
+        let composer = Composer::with_state_provider(Network::Testnet, Arc::new(state), None);
+        let execution_result = composer
+            .call_view(
+                "0x1::stability_pool::account_deposit",
+                vec![],
+                vec![String::from(
+                    "0xa0fc6038965061835c42e8b8b0528841d492d3fb8f6d9e2105c613652ba9f5ce",
+                )],
+                375991494,
+            )
+            .unwrap();
+        assert_eq!(execution_result.return_values, vec![json!("0")]);
+        assert_eq!(execution_result.ledger_version, 375991494);
+    }
+
+    #[test]
+    fn test_transaction_context_native() {
+        let mut state = InMemoryStateProvider::new(4, 10);
+        state.add_module(
+            module_id("transaction_context"),
+            native_module(
+                "transaction_context",
+                "get_script_hash",
+                vec![],
+                vec![SignatureToken::Vector(Box::new(SignatureToken::U8))],
+            ),
+        );
+        let composer = Composer::with_state_provider(Network::Localnet, Arc::new(state), None);
+        let execution_result = composer
+            .call_view("0x1::transaction_context::call", vec![], vec![], 0)
+            .unwrap();
+        // A view runs without a script
+        assert_eq!(execution_result.return_values, vec![json!([])]);
+    }
+
+    #[test]
+    fn test_coin_supply_from_aggregator() {
+        let handle = AccountAddress::from_hex_literal("0x2").unwrap();
+        let key = AccountAddress::from_hex_literal("0x3").unwrap();
+        let mut aggregator = TestModule::new("aggregator");
+        let aggregator_struct = aggregator.add_struct(
+            "Aggregator",
+            AbilitySet::EMPTY | Ability::Store | Ability::Drop,
+            vec![
+                ("handle", SignatureToken::Address),
+                ("key", SignatureToken::Address),
+                ("limit", SignatureToken::U128),
+            ],
+        );
+        let aggregator_type = SignatureToken::Struct(StructHandleIndex(aggregator_struct.0));
+        let read = aggregator.add_function(
+            "read",
+            vec![],
+            (
+                vec![SignatureToken::Reference(Box::new(aggregator_type.clone()))],
+                vec![SignatureToken::U128],
+            ),
+            None,
+        );
+        let handle_constant = aggregator.add_address_constant(handle);
+        let key_constant = aggregator.add_address_constant(key);
+        // Reads the aggregator `(0x2, 0x3)` as `0x1::coin::supply<T>` reads the supply
+        aggregator.add_function(
+            "supply",
+            vec![AbilitySet::EMPTY],
+            (vec![], vec![SignatureToken::U128]),
+            Some((
+                vec![aggregator_type],
+                vec![
+                    Bytecode::LdConst(handle_constant),
+                    Bytecode::LdConst(key_constant),
+                    Bytecode::LdU128(u128::MAX),
+                    Bytecode::Pack(aggregator_struct),
+                    Bytecode::StLoc(0),
+                    Bytecode::ImmBorrowLoc(0),
+                    Bytecode::Call(read),
+                    Bytecode::Ret,
+                ],
+            )),
+        );
+        let mut state = InMemoryStateProvider::new(4, 10);
+        state.add_module(module_id("aggregator"), aggregator.bytecode());
+        state.add_table_item(
+            TableHandle(handle),
+            key.to_vec(),
+            bcs::to_bytes(&42u128).unwrap(),
+        );
+        let composer = Composer::with_state_provider(Network::Localnet, Arc::new(state), None);
+        let execution_result = composer
+            .call_view(
+                "0x1::aggregator::supply",
+                vec![String::from("u64")],
+                vec![],
+                0,
+            )
+            .unwrap();
+        assert_eq!(execution_result.return_values, vec![json!("42")]);
+    }
+
+    #[test]
+    fn test_reuse_composer_across_calls() {
+        let composer =
+            Composer::with_state_provider(Network::Mainnet, Arc::new(block_state(1)), None);
+        for _ in 0..2 {
+            let execution_result = composer
+                .call_view("0x1::block::get_current_block_height", vec![], vec![], 0)
+                .unwrap();
+            assert_eq!(execution_result.return_values, vec![json!("7")]);
+        }
+    }
+
+    // Reads the mainnet, run it with `cargo test -- --ignored`
+    #[test]
+    #[ignore]
+    fn test_live_get_current_block_height() {
+        let composer = Composer::new(Network::Mainnet, &CONFIG).unwrap();
+        let execution_result = composer
+            .call_view("0x1::block::get_current_block_height", vec![], vec![], 0)
+            .unwrap();
+        assert_eq!(execution_result.return_values.len(), 1);
+        assert_eq!(execution_result.chain_id, Some(1));
+        assert!(execution_result.block_timestamp_usecs.is_some());
+        debug!("{}", execution_result.return_values[0]);
+    }
+
+    #[test]
+    fn test_record_and_replay_aborted_call() {
+        let path = std::env::temp_dir().join(format!("fixture_{}.json", Uuid::new_v4()));
+        let mut state = InMemoryStateProvider::new(4, 10);
+        state.add_module(
+            ModuleId::new(CORE_CODE_ADDRESS, Identifier::new("failures").unwrap()),
+            module_bytecode(
+                "failures",
+                vec![(
+                    "fail",
+                    vec![],
+                    vec![SignatureToken::U64],
+                    vec![Bytecode::LdU64(7), Bytecode::Abort],
+                )],
+            ),
+        );
+        let aborted = ComposerError::VmAbort {
+            location: String::from("0x1::failures"),
+            code: 7,
+        };
+
+        let fixture = FixtureStore::record(&path).unwrap();
+        let provider = RecordingStateProvider::new(Arc::new(state), fixture.clone());
+        let composer = Composer {
+            fixture: Some(fixture),
+            ..Composer::with_state_provider(Network::Localnet, Arc::new(provider), None)
+        };
+        let err = composer
+            .call_view("0x1::failures::fail", vec![], vec![], 0)
+            .unwrap_err();
+        assert_eq!(err, aborted);
+
+        let replayer = FixtureStore::replay(&path).unwrap();
+        let composer = Composer::with_state_provider(Network::Localnet, Arc::new(replayer), None);
+        let err = composer
+            .call_view("0x1::failures::fail", vec![], vec![], 0)
+            .unwrap_err();
+        assert_eq!(err, aborted);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_verify_chain_id() {
+        let provider = Arc::new(InMemoryStateProvider::new(4, 10));
+        let composer = Composer::with_state_provider(Network::Localnet, provider.clone(), Some(4));
+        assert_eq!(composer.chain_id().unwrap(), 4);
+
+        let composer = Composer::with_state_provider(Network::Mainnet, provider.clone(), Some(1));
+        let mismatch = ComposerError::ChainIdMismatch {
+            network: String::from("mainnet"),
+            expected: 1,
+            actual: 4,
+        };
+        assert_eq!(composer.chain_id().unwrap_err(), mismatch);
+        // The calls are refused before reading anything else
+        assert_eq!(
+            composer
+                .call_view("0x1::block::get_current_block_height", vec![], vec![], 0)
+                .unwrap_err(),
+            mismatch
+        );
+
+        let composer = Composer::with_state_provider(Network::Mainnet, provider, None);
+        assert_eq!(composer.chain_id().unwrap(), 4);
+    }
+}
@@ -10,11 +10,68 @@ pub struct ConfigData {
     pub config: ToolConfig,
 }
 
+/// Endpoints of a network. In the config file it's either the node url alone, or a table like
+/// `{ url = "http://127.0.0.1:8080", chain_id = 4 }`.
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(from = "NetworkConfigData")]
+pub struct NetworkConfig {
+    /// Url of the fullnode REST API.
+    pub url: String,
+    /// Chain id served by the network, if it's known.
+    pub chain_id: Option<u8>,
+    /// Url of the node serving the table items, the fullnode by default.
+    pub table_url: Option<String>,
+    /// Url of the GraphQL endpoint of the indexer of the network, for the tools built on top.
+    pub indexer_url: Option<String>,
+}
+
+impl NetworkConfig {
+    pub fn new(url: &str, chain_id: Option<u8>) -> Self {
+        Self {
+            url: String::from(url),
+            chain_id,
+            table_url: None,
+            indexer_url: None,
+        }
+    }
+}
+
 #[derive(Deserialize)]
+#[serde(untagged)]
+enum NetworkConfigData {
+    Url(String),
+    Full {
+        url: String,
+        chain_id: Option<u8>,
+        table_url: Option<String>,
+        indexer_url: Option<String>,
+    },
+}
+
+impl From<NetworkConfigData> for NetworkConfig {
+    fn from(data: NetworkConfigData) -> Self {
+        match data {
+            NetworkConfigData::Url(url) => NetworkConfig::new(url.as_str(), None),
+            NetworkConfigData::Full {
+                url,
+                chain_id,
+                table_url,
+                indexer_url,
+            } => NetworkConfig {
+                url,
+                chain_id,
+                table_url,
+                indexer_url,
+            },
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
 pub struct ToolConfig {
     pub log_folder: Option<String>,
     pub cache_folder: Option<String>,
-    pub network_configs: HashMap<Network, String>,
+    pub network_configs: HashMap<Network, NetworkConfig>,
     #[serde(default)]
     pub enable_module_caching: bool,
 }
@@ -24,15 +81,20 @@ impl ToolConfig {
         let mut network_configs = HashMap::new();
         network_configs.insert(
             Network::Mainnet,
-            String::from("https://fullnode.mainnet.aptoslabs.com"),
+            NetworkConfig::new("https://fullnode.mainnet.aptoslabs.com", Some(1)),
         );
         network_configs.insert(
             Network::Testnet,
-            String::from("https://fullnode.testnet.aptoslabs.com"),
+            NetworkConfig::new("https://fullnode.testnet.aptoslabs.com", Some(2)),
         );
+        // The chain id of devnet changes with each of its resets
         network_configs.insert(
             Network::Devnet,
-            String::from("https://fullnode.devnet.aptoslabs.com"),
+            NetworkConfig::new("https://fullnode.devnet.aptoslabs.com", None),
+        );
+        network_configs.insert(
+            Network::Localnet,
+            NetworkConfig::new("http://127.0.0.1:8080", Some(4)),
         );
         let home_path = match home::home_dir() {
             Some(path) => path.into_os_string().into_string().unwrap(),
@@ -74,10 +136,19 @@ impl ConfigData {
             default_config.config.cache_folder = Some(cache_folder)
         }
         default_config.config.enable_module_caching = data.config.enable_module_caching;
-        default_config
-            .config
-            .network_configs
-            .extend(data.config.network_configs);
+        for (network, mut network_config) in data.config.network_configs {
+            // Overriding only the url of a known network keeps its chain id
+            if let Some(default_network_config) =
+                default_config.config.network_configs.get(&network)
+            {
+                network_config.chain_id =
+                    network_config.chain_id.or(default_network_config.chain_id);
+            }
+            default_config
+                .config
+                .network_configs
+                .insert(network, network_config);
+        }
         default_config
     }
 
@@ -98,3 +169,36 @@ impl ConfigData {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{ConfigData, NetworkConfig};
+    use crate::types::Network;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_named_networks() {
+        let data: ConfigData = toml::from_str(
+            r#"
+            [config]
+            network_configs = { mainnet = "https://node.example.com", MyChain = { url = "http://127.0.0.1:8080", chain_id = 35, table_url = "http://127.0.0.1:8081", indexer_url = "http://127.0.0.1:8090/v1/graphql" } }
+            "#,
+        )
+        .unwrap();
+        let network_configs = data.config.network_configs;
+        assert_eq!(
+            network_configs[&Network::Mainnet],
+            NetworkConfig::new("https://node.example.com", None)
+        );
+        let my_chain = &network_configs[&Network::Named(String::from("mychain"))];
+        assert_eq!(my_chain.chain_id, Some(35));
+        assert_eq!(my_chain.table_url.as_deref(), Some("http://127.0.0.1:8081"));
+        assert_eq!(
+            my_chain.indexer_url.as_deref(),
+            Some("http://127.0.0.1:8090/v1/graphql")
+        );
+
+        assert_eq!(Network::from_str("Localnet").unwrap(), Network::Localnet);
+        assert!(Network::from_str("not a network").is_err());
+    }
+}
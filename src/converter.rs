@@ -1,13 +1,14 @@
+use crate::aggregator::{is_aggregator, resolve_aggregator_fields};
+use crate::error::ComposerError;
 use crate::module_resolver::CacheModuleResolver;
-use crate::types::Network;
+use crate::state_provider::StateProvider;
 use anyhow::{anyhow, Result};
 use aptos_sdk::rest_client::aptos_api_types::MoveType;
-use aptos_sdk::rest_client::Client;
 use move_core_types::account_address::AccountAddress;
 use move_core_types::identifier::Identifier;
-use move_core_types::language_storage::{ModuleId, TypeTag};
+use move_core_types::language_storage::ModuleId;
 use move_core_types::value::{MoveStruct, MoveValue};
-use serde_json::{json, Map, Value};
+use serde_json::{Map, Value};
 use std::borrow::Borrow;
 use std::str::FromStr;
 
@@ -38,22 +39,28 @@ pub fn move_value_to_json(val: MoveValue) -> Value {
     }
 }
 
+/// Names the fields of the struct values after the ABI of `t`. Aggregators also get their value
+/// resolved from `provider`, as it's not kept in the struct.
 pub fn annotate_value(
     val: MoveValue,
     t: &MoveType,
     module_resolver: &CacheModuleResolver,
-) -> MoveValue {
+    provider: &dyn StateProvider,
+    ledger_version: u64,
+) -> Result<MoveValue, ComposerError> {
     let mut annotated_value = val;
     match t {
         MoveType::Struct(struct_tag) => {
             let module = ModuleId::new(
-                AccountAddress::from_bytes(struct_tag.address.inner().into_bytes()).unwrap(),
-                Identifier::from_str(struct_tag.module.as_str()).unwrap(),
+                AccountAddress::from_bytes(struct_tag.address.inner().into_bytes())
+                    .map_err(|e| ComposerError::Conversion(e.to_string()))?,
+                Identifier::from_str(struct_tag.module.as_str())
+                    .map_err(|e| ComposerError::Conversion(e.to_string()))?,
             );
-            let (_, abi) = module_resolver.get_module(&module).unwrap();
+            let (_, abi) = module_resolver.get_module(&module, ledger_version)?;
 
             let fields_found = if let Some(ms) = abi
-                .unwrap()
+                .ok_or_else(|| ComposerError::ModuleNotFound(module.short_str_lossless()))?
                 .structs
                 .into_iter()
                 .find(|s| s.name.to_string() == struct_tag.name.to_string())
@@ -66,20 +73,42 @@ pub fn annotate_value(
             annotated_value = match annotated_value {
                 MoveValue::Struct(MoveStruct::Runtime(struct_vals)) => {
                     if let Some(fields) = fields_found {
-                        let mut fields_iter = fields.into_iter();
-                        MoveValue::Struct(MoveStruct::WithFields(
-                            struct_vals
-                                .into_iter()
-                                .map(|v| {
-                                    let field = fields_iter.next().unwrap();
-                                    let id =
-                                        Identifier::from_str(field.name.0.into_string().as_str())
-                                            .unwrap();
-                                    let inner_tp: MoveType = field.typ;
-                                    (id, annotate_value(v, &inner_tp, module_resolver))
-                                })
-                                .collect(),
-                        ))
+                        if fields.len() != struct_vals.len() {
+                            return Err(ComposerError::Conversion(format!(
+                                "Expect {} fields for struct {}, got {}",
+                                fields.len(),
+                                struct_tag,
+                                struct_vals.len()
+                            )));
+                        }
+                        let mut annotated_fields = vec![];
+                        for (v, field) in struct_vals.into_iter().zip(fields.into_iter()) {
+                            let id = Identifier::from_str(field.name.0.into_string().as_str())
+                                .map_err(|e| ComposerError::Conversion(e.to_string()))?;
+                            let inner_tp: MoveType = field.typ;
+                            annotated_fields.push((
+                                id,
+                                annotate_value(
+                                    v,
+                                    &inner_tp,
+                                    module_resolver,
+                                    provider,
+                                    ledger_version,
+                                )?,
+                            ));
+                        }
+                        if is_aggregator(
+                            module.address(),
+                            module.name().as_str(),
+                            struct_tag.name.as_str(),
+                        ) {
+                            annotated_fields = resolve_aggregator_fields(
+                                annotated_fields,
+                                provider,
+                                ledger_version,
+                            )?;
+                        }
+                        MoveValue::Struct(MoveStruct::WithFields(annotated_fields))
                     } else {
                         MoveValue::Struct(MoveStruct::Runtime(struct_vals))
                     }
@@ -95,17 +124,30 @@ pub fn annotate_value(
                     MoveValue::Vector(inner_vals) => MoveValue::Vector(
                         inner_vals
                             .into_iter()
-                            .map(|v| annotate_value(v, items.borrow(), module_resolver))
-                            .collect(),
+                            .map(|v| {
+                                annotate_value(
+                                    v,
+                                    items.borrow(),
+                                    module_resolver,
+                                    provider,
+                                    ledger_version,
+                                )
+                            })
+                            .collect::<Result<Vec<_>, _>>()?,
                     ),
-                    _ => panic!("Expect vector value here"),
+                    _ => {
+                        return Err(ComposerError::Conversion(format!(
+                            "Expect vector value for type {}",
+                            t
+                        )))
+                    }
                 };
             }
             _ => {}
         },
         _ => {}
     }
-    annotated_value
+    Ok(annotated_value)
 }
 
 fn struct_fields_to_json(fields: Vec<(Identifier, MoveValue)>) -> Value {
@@ -148,7 +190,6 @@ fn vec_to_vec_u8(vec: Vec<MoveValue>) -> Result<Vec<u8>> {
 #[cfg(test)]
 mod tests {
     use crate::converter::move_value_to_json;
-    use move_core_types::account_address::AccountAddress;
     use move_core_types::value::MoveValue;
     use serde_json::json;
 
@@ -0,0 +1,116 @@
+use move_binary_format::errors::{Location, VMError};
+use move_core_types::vm_status::StatusCode;
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+use thiserror::Error;
+
+/// Errors surfaced by the tool. Each variant maps to a stable error code, so the consumers of the
+/// execution result don't need to parse the message.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ComposerError {
+    #[error("Invalid input: {0}")]
+    InvalidInput(String),
+    #[error("Network error: {0}")]
+    Network(String),
+    #[error("Expect chain id {expected} on network {network}, got chain id {actual}")]
+    ChainIdMismatch {
+        network: String,
+        expected: u8,
+        actual: u8,
+    },
+    #[error("Module {0} is not found")]
+    ModuleNotFound(String),
+    #[error("Function {0} is not found")]
+    FunctionNotFound(String),
+    #[error("Execution aborted in {location} with code {code}")]
+    VmAbort { location: String, code: u64 },
+    #[error("Execution ran out of gas with a budget of {gas_budget} gas units")]
+    OutOfGas { gas_budget: u64 },
+    #[error("Execution failed with status {status}: {message}")]
+    VmError { status: String, message: String },
+    #[error("Conversion error: {0}")]
+    Conversion(String),
+    #[error("Internal error: {0}")]
+    Internal(String),
+}
+
+impl ComposerError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ComposerError::InvalidInput(_) => "INVALID_INPUT",
+            ComposerError::Network(_) => "NETWORK_ERROR",
+            ComposerError::ChainIdMismatch { .. } => "CHAIN_ID_MISMATCH",
+            ComposerError::ModuleNotFound(_) => "MODULE_NOT_FOUND",
+            ComposerError::FunctionNotFound(_) => "FUNCTION_NOT_FOUND",
+            ComposerError::VmAbort { .. } => "VM_ABORT",
+            ComposerError::OutOfGas { .. } => "OUT_OF_GAS",
+            ComposerError::VmError { .. } => "VM_ERROR",
+            ComposerError::Conversion(_) => "CONVERSION_ERROR",
+            ComposerError::Internal(_) => "INTERNAL_ERROR",
+        }
+    }
+}
+
+impl Serialize for ComposerError {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("ComposerError", 4)?;
+        state.serialize_field("code", self.code())?;
+        state.serialize_field("message", self.to_string().as_str())?;
+        match self {
+            ComposerError::VmAbort { location, code } => {
+                state.serialize_field("location", location)?;
+                state.serialize_field("abort_code", code)?;
+            }
+            ComposerError::OutOfGas { gas_budget } => {
+                state.serialize_field("gas_budget", gas_budget)?;
+            }
+            ComposerError::ChainIdMismatch {
+                expected, actual, ..
+            } => {
+                state.serialize_field("expected_chain_id", expected)?;
+                state.serialize_field("chain_id", actual)?;
+            }
+            _ => {}
+        }
+        state.end()
+    }
+}
+
+impl From<reqwest::Error> for ComposerError {
+    fn from(err: reqwest::Error) -> Self {
+        ComposerError::Network(err.to_string())
+    }
+}
+
+impl From<aptos_sdk::rest_client::error::RestError> for ComposerError {
+    fn from(err: aptos_sdk::rest_client::error::RestError) -> Self {
+        ComposerError::Network(err.to_string())
+    }
+}
+
+impl From<VMError> for ComposerError {
+    fn from(err: VMError) -> Self {
+        let location = match err.location() {
+            Location::Undefined => String::from("undefined"),
+            Location::Script => String::from("script"),
+            Location::Module(module_id) => module_id.short_str_lossless(),
+        };
+        if err.major_status() == StatusCode::ABORTED {
+            ComposerError::VmAbort {
+                location,
+                code: err.sub_status().unwrap_or_default(),
+            }
+        } else {
+            ComposerError::VmError {
+                status: format!("{:?}", err.major_status()),
+                message: err
+                    .message()
+                    .cloned()
+                    .unwrap_or_else(|| format!("in {}", location)),
+            }
+        }
+    }
+}
@@ -0,0 +1,366 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use log::debug;
+use move_binary_format::CompiledModule;
+use move_core_types::account_address::AccountAddress;
+use move_core_types::language_storage::{ModuleId, StructTag};
+use move_table_extension::TableHandle;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::error::ComposerError;
+use crate::state_provider::StateProvider;
+
+/// Chain state read by executions, stored as hex encoded bytes. `None` records that the item
+/// doesn't exist at that version.
+#[derive(Serialize, Deserialize, Default, Debug, PartialEq, Eq)]
+pub struct Fixture {
+    #[serde(default)]
+    pub chain_id: Option<u8>,
+    pub latest_ledger_version: Option<u64>,
+    pub modules: BTreeMap<String, Option<String>>,
+    pub resources: BTreeMap<String, Option<String>>,
+    pub table_items: BTreeMap<String, Option<String>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FixtureMode {
+    Record,
+    Replay,
+}
+
+/// Records the chain state read by executions into a fixture file, or serves it back from that
+/// file without any network access. Recording goes through `RecordingStateProvider`, replaying
+/// uses the store itself as the `StateProvider`.
+#[derive(Clone)]
+pub struct FixtureStore {
+    mode: FixtureMode,
+    path: PathBuf,
+    fixture: Arc<Mutex<Fixture>>,
+}
+
+impl FixtureStore {
+    /// Records into `path`. An existing fixture is extended, so several calls can share one file.
+    pub fn record(path: impl AsRef<Path>) -> Result<Self, ComposerError> {
+        let fixture = if path.as_ref().exists() {
+            Self::load(path.as_ref())?
+        } else {
+            Fixture::default()
+        };
+        Ok(Self {
+            mode: FixtureMode::Record,
+            path: path.as_ref().to_path_buf(),
+            fixture: Arc::new(Mutex::new(fixture)),
+        })
+    }
+
+    pub fn replay(path: impl AsRef<Path>) -> Result<Self, ComposerError> {
+        Ok(Self {
+            mode: FixtureMode::Replay,
+            path: path.as_ref().to_path_buf(),
+            fixture: Arc::new(Mutex::new(Self::load(path.as_ref())?)),
+        })
+    }
+
+    pub fn is_replay(&self) -> bool {
+        self.mode == FixtureMode::Replay
+    }
+
+    /// Writes the recorded state to the fixture file, replays leave the file untouched.
+    pub fn save(&self) -> Result<(), ComposerError> {
+        if self.is_replay() {
+            return Ok(());
+        }
+        let content = serde_json::to_string_pretty(&*self.fixture.lock())
+            .map_err(|e| ComposerError::Conversion(e.to_string()))?;
+        fs::write(&self.path, content).map_err(|e| {
+            ComposerError::InvalidInput(format!(
+                "Cannot write the fixture file {}: {}",
+                self.path.display(),
+                e
+            ))
+        })
+    }
+
+    pub fn record_chain_id(&self, chain_id: u8) {
+        self.fixture.lock().chain_id = Some(chain_id);
+    }
+
+    pub fn record_latest_ledger_version(&self, ledger_version: u64) {
+        self.fixture.lock().latest_ledger_version = Some(ledger_version);
+    }
+
+    pub fn record_module(&self, module_id: &ModuleId, ledger_version: u64, bytes: Option<&[u8]>) {
+        let key = module_key(module_id, ledger_version);
+        self.fixture
+            .lock()
+            .modules
+            .insert(key, bytes.map(hex::encode));
+    }
+
+    pub fn record_resource(
+        &self,
+        address: &AccountAddress,
+        tag: &StructTag,
+        ledger_version: u64,
+        bytes: Option<&[u8]>,
+    ) {
+        let key = resource_key(address, tag, ledger_version);
+        self.fixture
+            .lock()
+            .resources
+            .insert(key, bytes.map(hex::encode));
+    }
+
+    pub fn record_table_item(
+        &self,
+        handle: &TableHandle,
+        key: &[u8],
+        ledger_version: u64,
+        bytes: Option<&[u8]>,
+    ) {
+        let key = table_item_key(handle, key, ledger_version);
+        self.fixture
+            .lock()
+            .table_items
+            .insert(key, bytes.map(hex::encode));
+    }
+
+    fn load(path: &Path) -> Result<Fixture, ComposerError> {
+        let content = fs::read_to_string(path).map_err(|e| {
+            ComposerError::InvalidInput(format!(
+                "Cannot read the fixture file {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        serde_json::from_str(content.as_str()).map_err(|e| {
+            ComposerError::InvalidInput(format!("Invalid fixture file {}: {}", path.display(), e))
+        })
+    }
+
+    fn decode(
+        &self,
+        key: String,
+        value: Option<Option<String>>,
+    ) -> Result<Option<Vec<u8>>, ComposerError> {
+        debug!("load {} from fixture {}", key, self.path.display());
+        match value {
+            Some(Some(bytes)) => hex::decode(bytes)
+                .map(Some)
+                .map_err(|e| ComposerError::Conversion(e.to_string())),
+            Some(None) => Ok(None),
+            None => Err(self.not_recorded(key.as_str())),
+        }
+    }
+
+    fn not_recorded(&self, key: &str) -> ComposerError {
+        ComposerError::Network(format!(
+            "{} is not recorded in the fixture {}",
+            key,
+            self.path.display()
+        ))
+    }
+}
+
+/// Replays the recorded state, any read missing from the fixture fails.
+impl StateProvider for FixtureStore {
+    fn latest_version(&self) -> Result<u64, ComposerError> {
+        self.fixture
+            .lock()
+            .latest_ledger_version
+            .ok_or_else(|| self.not_recorded("the latest ledger version"))
+    }
+
+    fn chain_id(&self) -> Result<u8, ComposerError> {
+        self.fixture
+            .lock()
+            .chain_id
+            .ok_or_else(|| self.not_recorded("the chain id"))
+    }
+
+    fn get_module(
+        &self,
+        module_id: &ModuleId,
+        ledger_version: u64,
+    ) -> Result<Option<Vec<u8>>, ComposerError> {
+        let key = module_key(module_id, ledger_version);
+        let value = self.fixture.lock().modules.get(&key).cloned();
+        self.decode(key, value)
+    }
+
+    fn get_resource(
+        &self,
+        address: &AccountAddress,
+        tag: &StructTag,
+        ledger_version: u64,
+    ) -> Result<Option<Vec<u8>>, ComposerError> {
+        let key = resource_key(address, tag, ledger_version);
+        let value = self.fixture.lock().resources.get(&key).cloned();
+        self.decode(key, value)
+    }
+
+    fn get_table_item(
+        &self,
+        handle: &TableHandle,
+        key: &[u8],
+        ledger_version: u64,
+    ) -> Result<Option<Vec<u8>>, ComposerError> {
+        let key = table_item_key(handle, key, ledger_version);
+        let value = self.fixture.lock().table_items.get(&key).cloned();
+        self.decode(key, value)
+    }
+}
+
+/// Reads the state from another provider and records every read into a fixture.
+pub struct RecordingStateProvider {
+    inner: Arc<dyn StateProvider>,
+    fixture: FixtureStore,
+}
+
+impl RecordingStateProvider {
+    pub fn new(inner: Arc<dyn StateProvider>, fixture: FixtureStore) -> Self {
+        Self { inner, fixture }
+    }
+}
+
+impl StateProvider for RecordingStateProvider {
+    fn latest_version(&self) -> Result<u64, ComposerError> {
+        let ledger_version = self.inner.latest_version()?;
+        self.fixture.record_latest_ledger_version(ledger_version);
+        Ok(ledger_version)
+    }
+
+    fn chain_id(&self) -> Result<u8, ComposerError> {
+        let chain_id = self.inner.chain_id()?;
+        self.fixture.record_chain_id(chain_id);
+        Ok(chain_id)
+    }
+
+    fn get_module(
+        &self,
+        module_id: &ModuleId,
+        ledger_version: u64,
+    ) -> Result<Option<Vec<u8>>, ComposerError> {
+        let bytecode = self.inner.get_module(module_id, ledger_version)?;
+        self.fixture
+            .record_module(module_id, ledger_version, bytecode.as_deref());
+        Ok(bytecode)
+    }
+
+    fn get_resource(
+        &self,
+        address: &AccountAddress,
+        tag: &StructTag,
+        ledger_version: u64,
+    ) -> Result<Option<Vec<u8>>, ComposerError> {
+        let resource = self.inner.get_resource(address, tag, ledger_version)?;
+        self.fixture
+            .record_resource(address, tag, ledger_version, resource.as_deref());
+        Ok(resource)
+    }
+
+    fn get_table_item(
+        &self,
+        handle: &TableHandle,
+        key: &[u8],
+        ledger_version: u64,
+    ) -> Result<Option<Vec<u8>>, ComposerError> {
+        let item = self.inner.get_table_item(handle, key, ledger_version)?;
+        self.fixture
+            .record_table_item(handle, key, ledger_version, item.as_deref());
+        Ok(item)
+    }
+
+    // Recorded as single module reads, which is how a replay reads them back
+    fn get_account_modules(
+        &self,
+        address: &AccountAddress,
+        ledger_version: u64,
+    ) -> Result<Vec<Vec<u8>>, ComposerError> {
+        let modules = self.inner.get_account_modules(address, ledger_version)?;
+        for bytecode in &modules {
+            let module_id = CompiledModule::deserialize(bytecode)
+                .map_err(|e| ComposerError::Conversion(e.to_string()))?
+                .self_id();
+            self.fixture
+                .record_module(&module_id, ledger_version, Some(bytecode));
+        }
+        Ok(modules)
+    }
+}
+
+fn module_key(module_id: &ModuleId, ledger_version: u64) -> String {
+    format!("{}@{}", module_id.short_str_lossless(), ledger_version)
+}
+
+fn resource_key(address: &AccountAddress, tag: &StructTag, ledger_version: u64) -> String {
+    format!("{}/{}@{}", address.to_hex_literal(), tag, ledger_version)
+}
+
+fn table_item_key(handle: &TableHandle, key: &[u8], ledger_version: u64) -> String {
+    format!(
+        "{}/{}@{}",
+        handle.0.to_hex_literal(),
+        hex::encode(key),
+        ledger_version
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fixture::FixtureStore;
+    use crate::state_provider::StateProvider;
+    use move_core_types::account_address::AccountAddress;
+    use move_core_types::identifier::Identifier;
+    use move_core_types::language_storage::{ModuleId, StructTag, CORE_CODE_ADDRESS};
+    use move_table_extension::TableHandle;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_record_and_replay() {
+        let table_handle = TableHandle(AccountAddress::from_hex_literal("0x2").unwrap());
+        let path = std::env::temp_dir().join(format!("fixture_{}.json", Uuid::new_v4()));
+        let module_id = ModuleId::new(CORE_CODE_ADDRESS, Identifier::new("coin").unwrap());
+        let tag = StructTag {
+            address: CORE_CODE_ADDRESS,
+            module: Identifier::new("block").unwrap(),
+            name: Identifier::new("BlockResource").unwrap(),
+            type_params: vec![],
+        };
+
+        let recorder = FixtureStore::record(&path).unwrap();
+        recorder.record_chain_id(2);
+        recorder.record_latest_ledger_version(10);
+        recorder.record_module(&module_id, 10, Some(&[1, 2, 3]));
+        recorder.record_resource(&CORE_CODE_ADDRESS, &tag, 10, None);
+        recorder.record_table_item(&table_handle, &[4], 10, Some(&[5]));
+        recorder.save().unwrap();
+
+        let replayer = FixtureStore::replay(&path).unwrap();
+        assert!(replayer.is_replay());
+        assert_eq!(replayer.chain_id().unwrap(), 2);
+        assert_eq!(replayer.latest_version().unwrap(), 10);
+        assert_eq!(
+            replayer.get_module(&module_id, 10).unwrap(),
+            Some(vec![1, 2, 3])
+        );
+        assert_eq!(
+            replayer.get_resource(&CORE_CODE_ADDRESS, &tag, 10).unwrap(),
+            None
+        );
+        assert_eq!(
+            replayer.get_table_item(&table_handle, &[4], 10).unwrap(),
+            Some(vec![5])
+        );
+        // Reads which were not recorded fail instead of reaching the network
+        assert_eq!(
+            replayer.get_module(&module_id, 11).unwrap_err().code(),
+            "NETWORK_ERROR"
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -1,17 +1,17 @@
-use crate::config::ToolConfig;
+use crate::arguments::{encode_argument, substitute_type_params};
+use crate::config::{NetworkConfig, ToolConfig};
+use crate::error::ComposerError;
+use crate::module_resolver::CacheModuleResolver;
+use crate::state_provider::StateProvider;
 use crate::types::Network;
-use aptos_sdk::rest_client::aptos_api_types::{MoveModule, MoveType};
-use aptos_sdk::rest_client::{Client, MoveModuleBytecode};
-use log::{debug, info, warn};
-use move_core_types::account_address::AccountAddress;
-use move_core_types::language_storage::ModuleId;
-use move_core_types::value::MoveValue;
+use aptos_sdk::rest_client::aptos_api_types::MoveType;
+use log::info;
 use path_clean::PathClean;
+use serde_json::Value;
 use std::env;
 use std::io;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use tokio::runtime::Runtime;
 use url::Url;
 
 pub fn absolute_path(path: impl AsRef<Path>) -> io::Result<PathBuf> {
@@ -27,97 +27,90 @@ pub fn absolute_path(path: impl AsRef<Path>) -> io::Result<PathBuf> {
     Ok(absolute_path)
 }
 
-pub fn get_node_url(network: &Network, config: &ToolConfig) -> Url {
-    if let Some(url) = config.network_configs.get(network) {
-        info!("Use client url: {}", url);
-        return Url::from_str(url.as_str()).unwrap();
+pub fn get_network_config<'a>(
+    network: &Network,
+    config: &'a ToolConfig,
+) -> Result<&'a NetworkConfig, ComposerError> {
+    config.network_configs.get(network).ok_or_else(|| {
+        ComposerError::InvalidInput(format!("Cannot find the URL of network {}", network))
+    })
+}
+
+pub fn get_node_url(network: &Network, config: &ToolConfig) -> Result<Url, ComposerError> {
+    let url = &get_network_config(network, config)?.url;
+    info!("Use client url: {}", url);
+    parse_url(network, url)
+}
+
+/// Url of the node serving the table items, the fullnode unless the network sets `table_url`.
+pub fn get_table_url(network: &Network, config: &ToolConfig) -> Result<Url, ComposerError> {
+    match &get_network_config(network, config)?.table_url {
+        Some(url) => parse_url(network, url),
+        None => get_node_url(network, config),
+    }
+}
+
+fn parse_url(network: &Network, url: &str) -> Result<Url, ComposerError> {
+    Url::from_str(url).map_err(|e| {
+        ComposerError::InvalidInput(format!("Invalid url of network {}: {}", network, e))
+    })
+}
+
+/// Builds the url of a REST endpoint on top of the configured node url, adding the `v1` prefix
+/// if the configured url doesn't carry it already.
+pub fn get_rest_url(node_url: &Url, path: &str) -> Url {
+    let mut base_url = node_url.clone();
+    let base_path = base_url.path().trim_end_matches('/').to_string();
+    if base_path.ends_with("/v1") {
+        base_url.set_path(format!("{}/", base_path).as_str());
+    } else {
+        base_url.set_path(format!("{}/v1/", base_path).as_str());
+    }
+    base_url.join(path).unwrap()
+}
+
+/// Resolves the ledger version the execution runs against. `0` means the latest version, which is
+/// read from the provider once so that all the reads of one execution observe the same state.
+pub fn resolve_ledger_version(
+    provider: &dyn StateProvider,
+    ledger_version: u64,
+) -> Result<u64, ComposerError> {
+    if ledger_version > 0 {
+        return Ok(ledger_version);
     }
-    panic!("Cannot find the network URL")
+    let latest_version = provider.latest_version()?;
+    info!("Pin the latest ledger version to {}", latest_version);
+    Ok(latest_version)
 }
 
 pub fn serialize_input_params(
-    raw_args: Option<Vec<String>>,
+    raw_args: Option<Vec<Value>>,
     param_types: Vec<MoveType>,
-) -> Vec<Vec<u8>> {
+    type_params: &[MoveType],
+    module_resolver: &CacheModuleResolver,
+    ledger_version: u64,
+    read_files: bool,
+) -> Result<Vec<Vec<u8>>, ComposerError> {
     let mut args: Vec<Vec<u8>> = Vec::new();
     if let Some(input_params) = raw_args {
-        assert_eq!(
-            input_params.len(),
-            param_types.len(),
-            "The length of provided input params is not equal to expected one."
-        );
-        let mut param_types_iter = param_types.into_iter();
-        input_params.into_iter().for_each(|p| {
-            if p.trim().len() > 0 {
-                let current_param_type = param_types_iter.next().unwrap();
-                match current_param_type {
-                    MoveType::Bool => {
-                        args.push(
-                            MoveValue::Bool(matches!(p.trim(), "true" | "t" | "1"))
-                                .simple_serialize()
-                                .unwrap(),
-                        );
-                    }
-                    MoveType::U8 => {
-                        let num = p.trim().parse::<u8>();
-                        args.push(MoveValue::U8(num.unwrap()).simple_serialize().unwrap());
-                    }
-                    MoveType::U64 => {
-                        let num = p.trim().parse::<u64>();
-                        args.push(MoveValue::U64(num.unwrap()).simple_serialize().unwrap());
-                    }
-                    MoveType::U128 => {
-                        let num = p.trim().parse::<u128>();
-                        args.push(MoveValue::U128(num.unwrap()).simple_serialize().unwrap());
-                    }
-                    MoveType::Address => {
-                        // Suppose it's an account parameter
-                        args.push(
-                            MoveValue::Address(AccountAddress::from_hex_literal(p.trim()).unwrap())
-                                .simple_serialize()
-                                .unwrap(),
-                        );
-                    }
-                    MoveType::Signer => {
-                        // Suppose it's an account parameter
-                        args.push(
-                            MoveValue::Signer(AccountAddress::from_hex_literal(p.trim()).unwrap())
-                                .simple_serialize()
-                                .unwrap(),
-                        );
-                    }
-                    MoveType::Vector { items } => match items.as_ref() {
-                        MoveType::Bool => {}
-                        MoveType::U8 => args.push(
-                            MoveValue::vector_u8(String::from(p.trim()).into_bytes())
-                                .simple_serialize()
-                                .unwrap(),
-                        ),
-                        MoveType::U64 => {}
-                        MoveType::U128 => {}
-                        MoveType::Address => {}
-                        MoveType::Signer => {}
-                        MoveType::Vector { .. } => {}
-                        MoveType::Struct(_) => {}
-                        MoveType::GenericTypeParam { .. } => {}
-                        MoveType::Reference { .. } => {}
-                        MoveType::Unparsable(_) => {}
-                    },
-                    MoveType::Struct(_tag) => {
-                        panic!("Struct type is not supported yet")
-                    }
-                    MoveType::GenericTypeParam { .. } => {
-                        panic!("Struct type is not supported yet")
-                    }
-                    MoveType::Reference { .. } => {
-                        panic!("Struct type is not supported yet")
-                    }
-                    MoveType::Unparsable(_) => {
-                        panic!("Unparsable paramter")
-                    }
-                }
-            }
-        });
+        if input_params.len() != param_types.len() {
+            return Err(ComposerError::InvalidInput(format!(
+                "The length of provided input params {} is not equal to expected one {}",
+                input_params.len(),
+                param_types.len()
+            )));
+        }
+        for (p, param_type) in input_params.into_iter().zip(param_types.iter()) {
+            // `T` is encoded as the type argument given for it
+            let param_type = substitute_type_params(param_type, type_params)?;
+            let value =
+                encode_argument(&p, &param_type, module_resolver, ledger_version, read_files)?;
+            args.push(
+                value.simple_serialize().ok_or_else(|| {
+                    ComposerError::Conversion(format!("Failed to serialize {}", p))
+                })?,
+            );
+        }
     }
-    return args;
+    Ok(args)
 }
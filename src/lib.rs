@@ -1,6 +1,25 @@
-mod config;
-mod converter;
-mod helper;
-mod module_resolver;
-mod storage;
-mod types;
+pub mod aggregator;
+pub mod arguments;
+pub mod config;
+pub mod converter;
+pub mod error;
+pub mod fixture;
+pub mod helper;
+pub mod logger;
+pub mod module_resolver;
+pub mod on_chain_config;
+pub mod resource_group;
+pub mod server;
+pub mod state_provider;
+pub mod storage;
+pub mod types;
+
+#[cfg(test)]
+mod test_utils;
+
+mod composer;
+
+pub use composer::Composer;
+pub use config::ToolConfig;
+pub use error::ComposerError;
+pub use types::{ExecutionResult, GasBudget, Network};
@@ -0,0 +1,104 @@
+use std::cell::RefCell;
+
+use log::{Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
+
+thread_local! {
+    // The lines logged by the current thread, while a capture is active
+    static CAPTURED: RefCell<Option<Vec<String>>> = RefCell::new(None);
+}
+
+/// Logs to `inner`, and also keeps the debug lines of the threads running `capture_logs`, so the
+/// server can return the logs of a single call whatever the log level of the file.
+pub struct CaptureLogger {
+    inner: Option<Box<dyn Log>>,
+}
+
+impl CaptureLogger {
+    /// Installs the logger. `inner` filters the records itself, the captures see the debug level
+    /// and above.
+    pub fn init(inner: Option<Box<dyn Log>>, level: LevelFilter) -> Result<(), SetLoggerError> {
+        log::set_boxed_logger(Box::new(Self { inner }))?;
+        log::set_max_level(level.max(LevelFilter::Debug));
+        Ok(())
+    }
+}
+
+impl Log for CaptureLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        (metadata.level() <= Level::Debug && is_capturing())
+            || self
+                .inner
+                .as_ref()
+                .map_or(false, |inner| inner.enabled(metadata))
+    }
+
+    fn log(&self, record: &Record) {
+        if record.level() <= Level::Debug {
+            let _ = CAPTURED.try_with(|captured| {
+                if let Some(lines) = captured.borrow_mut().as_mut() {
+                    lines.push(format!("[{}] {}", record.level(), record.args()));
+                }
+            });
+        }
+        if let Some(inner) = &self.inner {
+            inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(inner) = &self.inner {
+            inner.flush();
+        }
+    }
+}
+
+fn is_capturing() -> bool {
+    CAPTURED
+        .try_with(|captured| captured.borrow().is_some())
+        .unwrap_or(false)
+}
+
+// Ends the capture even if the captured function panics, the thread may run other calls later
+struct CaptureGuard;
+
+impl Drop for CaptureGuard {
+    fn drop(&mut self) {
+        let _ = CAPTURED.try_with(|captured| captured.borrow_mut().take());
+    }
+}
+
+/// Runs `f` and returns the lines it logged on the current thread, at the debug level and above.
+/// Only `CaptureLogger` records them.
+pub fn capture_logs<T>(f: impl FnOnce() -> T) -> (T, Vec<String>) {
+    CAPTURED.with(|captured| *captured.borrow_mut() = Some(vec![]));
+    let _guard = CaptureGuard;
+    let res = f();
+    let lines = CAPTURED
+        .with(|captured| captured.borrow_mut().take())
+        .unwrap_or_default();
+    (res, lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::logger::capture_logs;
+    use log::{debug, trace};
+
+    // The `CaptureLogger` is installed for all the tests, see the composer tests
+    #[test]
+    fn test_capture_logs() {
+        debug!("before the capture");
+        let (res, lines) = capture_logs(|| {
+            debug!("load module {}", "0x1::coin");
+            trace!("too verbose");
+            std::thread::spawn(|| debug!("another thread"))
+                .join()
+                .unwrap();
+            1
+        });
+        assert_eq!(res, 1);
+        assert_eq!(lines, vec![String::from("[DEBUG] load module 0x1::coin")]);
+        let (_, lines) = capture_logs(|| ());
+        assert!(lines.is_empty());
+    }
+}
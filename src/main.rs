@@ -1,14 +1,8 @@
-mod config;
-mod converter;
-mod helper;
-mod module_resolver;
-mod storage;
-mod types;
-
 extern crate core;
 extern crate log;
 
 use std::fs;
+use std::io::{self, Read};
 
 use simplelog::*;
 
@@ -16,42 +10,39 @@ use std::fs::File;
 use std::path::Path;
 use std::str::FromStr;
 
-use anyhow::Result;
-use aptos_gas::{AbstractValueSizeGasParameters, NativeGasParameters, LATEST_GAS_FEATURE_VERSION};
-use aptos_sdk::rest_client::Client;
-
 use clap::Parser;
 
-use log::{debug, LevelFilter};
-use move_core_types::account_address::AccountAddress;
-use move_core_types::identifier::{IdentStr, Identifier};
-use move_core_types::language_storage::{ModuleId, TypeTag, CORE_CODE_ADDRESS};
-use move_core_types::value::MoveValue;
-use move_vm_runtime::move_vm::MoveVM;
-use move_vm_test_utils::gas_schedule::{CostTable, Gas, GasStatus};
+use log::{debug, error, LevelFilter};
 use uuid::Uuid;
 
-use aptos_vm::natives;
-use move_table_extension::NativeTableContext;
-use move_vm_runtime::native_extensions::NativeContextExtensions;
-
-use crate::config::{ConfigData, ToolConfig};
-use crate::converter::{annotate_value, move_value_to_json};
-use crate::helper::{absolute_path, get_node_url, serialize_input_params};
-use crate::module_resolver::CacheModuleResolver;
-use crate::storage::InMemoryLazyStorage;
-use crate::types::{ExecutionResult, LogLevel, Network, ViewFunction};
+use serde_json::Value;
+use view_function::config::ConfigData;
+use view_function::fixture::FixtureStore;
+use view_function::helper::absolute_path;
+use view_function::logger::CaptureLogger;
+use view_function::server::{serve, ServerOptions};
+use view_function::types::{Command, LogLevel, ViewFunction, ViewRequest};
+use view_function::{Composer, ComposerError, ExecutionResult, GasBudget, Network, ToolConfig};
 
 fn main() {
     let command = ViewFunction::parse();
 
-    let func: String = command.function_id;
+    let func: Option<String> = command.function_id;
     let type_args: Option<Vec<String>> = command.type_args;
     let args: Option<Vec<String>> = command.args;
     let ledger_version: u64 = command.ledger_version;
     let network: Network = command.network;
     let config: Option<String> = command.config;
     let log_level: LogLevel = command.log_level;
+    let gas_budget = match (command.gas_budget, command.unmetered) {
+        (_, true) => GasBudget::Unmetered,
+        (Some(gas_budget), _) => GasBudget::Limit(gas_budget),
+        _ => GasBudget::Default,
+    };
+    let prefetch_modules: bool = command.prefetch_modules;
+    let record: Option<String> = command.record;
+    let replay: Option<String> = command.replay;
+    let request_json: Option<String> = command.request_json;
 
     let mut tool_config = ToolConfig::default();
     if let Some(config_file) = config {
@@ -60,36 +51,115 @@ fn main() {
         tool_config = load_config("config.toml");
     }
 
-    let log_path = set_up_log(&tool_config, format!("{}", log_level));
-
-    debug!("Value for func: {}", func);
-    if let Some(val) = type_args.clone() {
-        debug!("Value for type arguments: {:?}", val);
+    let is_server = matches!(command.command, Some(Command::Serve { .. }));
+    let log_path = set_up_log(&tool_config, format!("{}", log_level), is_server);
+
+    if let Some(Command::Serve {
+        host,
+        port,
+        static_dir,
+        rate_limit,
+    }) = command.command
+    {
+        let options = ServerOptions {
+            host,
+            port,
+            static_dir,
+            rate_limit,
+        };
+        if let Err(e) =
+            tokio::runtime::Runtime::new()
+                .unwrap()
+                .block_on(serve(tool_config, network, options))
+        {
+            error!("{}", e);
+            eprintln!("Failed to run the server: {}", e);
+            std::process::exit(1);
+        }
+        return;
     }
-    if let Some(val) = args.clone() {
-        debug!("Value for arguments: {:?}", val);
+    let request = match request_json {
+        Some(path) => read_request(path.as_str()),
+        // The function is required by clap when no subcommand or request file is given
+        None => Ok(ViewRequest {
+            function: func.unwrap(),
+            type_arguments: type_args.unwrap_or_default(),
+            arguments: args
+                .unwrap_or_default()
+                .into_iter()
+                .map(Value::String)
+                .collect(),
+            ledger_version: None,
+            network: None,
+        }),
+    };
+    let (ledger_version, network) = match &request {
+        Ok(request) => (
+            request.ledger_version.unwrap_or(ledger_version),
+            request.network.clone().unwrap_or(network),
+        ),
+        Err(_) => (ledger_version, network),
+    };
+
+    if let Ok(request) = &request {
+        debug!("Value for func: {}", request.function);
+        debug!("Value for type arguments: {:?}", request.type_arguments);
+        debug!("Value for arguments: {:?}", request.arguments);
     }
     debug!("Value for ledger version: {}", ledger_version);
     debug!("Value for network: {}", network);
     debug!("Value for log level: {}", log_level);
 
-    let mut execution_result = ExecutionResult {
-        log_path,
-        return_values: vec![],
+    let network_name = network.to_string();
+    let fixture = match (record, replay) {
+        (Some(path), _) => FixtureStore::record(path).map(Some),
+        (_, Some(path)) => FixtureStore::replay(path).map(Some),
+        _ => Ok(None),
     };
-    exec_func(
-        func,
-        type_args,
-        args,
-        ledger_version,
-        &network,
-        &tool_config,
-        &mut execution_result,
-    );
+    let mut execution_result = match request.and_then(|request| {
+        let mut composer = Composer::new_with_fixture(network, &tool_config, fixture?)?;
+        composer.set_gas_budget(gas_budget);
+        composer.set_prefetch_modules(prefetch_modules);
+        composer.set_read_files(true);
+        composer.call_view_json(
+            request.function.as_str(),
+            request.type_arguments,
+            request.arguments,
+            ledger_version,
+        )
+    }) {
+        Ok(res) => res,
+        Err(err) => {
+            error!("{}", err);
+            ExecutionResult {
+                ledger_version,
+                network: network_name,
+                error: Some(err),
+                ..Default::default()
+            }
+        }
+    };
+    execution_result.log_path = log_path;
     println!(
         "{}",
         serde_json::to_string_pretty(&execution_result).unwrap()
-    )
+    );
+    if execution_result.error.is_some() {
+        std::process::exit(1);
+    }
+}
+
+/// Reads the request of `--request-json`, from stdin if the path is `-`.
+fn read_request(path: &str) -> Result<ViewRequest, ComposerError> {
+    let content = if path == "-" {
+        let mut content = String::new();
+        io::stdin().read_to_string(&mut content).map(|_| content)
+    } else {
+        fs::read_to_string(path)
+    }
+    .map_err(|e| ComposerError::InvalidInput(format!("Cannot read the request {}: {}", path, e)))?;
+    serde_json::from_str(content.as_str())
+        .map_err(|e| ComposerError::InvalidInput(format!("Invalid request {}: {}", path, e)))
 }
 
 fn load_config(file_path: &str) -> ToolConfig {
@@ -99,8 +169,14 @@ fn load_config(file_path: &str) -> ToolConfig {
     ConfigData::default().config
 }
 
-fn set_up_log(config: &ToolConfig, log_level: String) -> String {
+/// Logs into a new file of the log folder and returns its path. The server also keeps the logs of
+/// each call, to return them on demand.
+fn set_up_log(config: &ToolConfig, log_level: String, capture: bool) -> String {
+    let level = LevelFilter::from_str(log_level.as_str()).unwrap();
     if log_level.as_str().eq_ignore_ascii_case("off") {
+        if capture {
+            CaptureLogger::init(None, level).unwrap();
+        }
         return String::new();
     }
     let dir = Path::new(config.log_folder.as_ref().unwrap().as_str());
@@ -112,258 +188,15 @@ fn set_up_log(config: &ToolConfig, log_level: String) -> String {
         .into_os_string()
         .into_string()
         .unwrap();
-    WriteLogger::init(
-        LevelFilter::from_str(log_level.as_str()).unwrap(),
-        Config::default(),
-        File::create(file_path.clone()).unwrap(),
-    )
-    .unwrap();
-    file_path
-}
-
-fn exec_func(
-    func: String,
-    type_args_input: Option<Vec<String>>,
-    args_input: Option<Vec<String>>,
-    ledger_version: u64,
-    network: &Network,
-    config: &ToolConfig,
-    execution_res: &mut ExecutionResult,
-) {
-    let mut splitted_func = func.split("::");
-    let account = AccountAddress::from_hex_literal(splitted_func.next().unwrap()).unwrap();
-    let module = ModuleId::new(
-        account,
-        Identifier::new(splitted_func.next().unwrap()).unwrap(),
-    );
-    let func_id = IdentStr::new(splitted_func.next().unwrap()).unwrap();
-
-    let client = Client::new(get_node_url(network, config));
-    let cache_folder = config.cache_folder.clone().unwrap();
-    let module_resolver = CacheModuleResolver::new(
-        network,
-        client.clone(),
-        cache_folder.clone(),
-        config.enable_module_caching,
-    );
-    let (_, abi) = module_resolver.get_module(&module).unwrap();
-    let matched_func = abi
-        .unwrap()
-        .exposed_functions
-        .into_iter()
-        .find(|f| f.name.to_string() == func_id.to_string());
-
-    let (param_types, ret_types) = if let Some(f) = matched_func {
-        (f.params, f.return_)
-    } else {
-        panic!("No matched function found!");
-    };
-
-    let ser_args: Vec<Vec<u8>> = serialize_input_params(args_input, param_types);
-
-    let type_args: Vec<TypeTag> = type_args_input
-        .unwrap_or(vec![])
-        .into_iter()
-        .map(|tp| TypeTag::from_str(tp.as_str()).unwrap())
-        .collect();
-
-    let storage = InMemoryLazyStorage::new(
-        ledger_version,
-        network.clone(),
-        client.clone(),
-        module_resolver.clone(),
-    );
-    let res = exec_func_internal(storage, module, func_id, type_args, ser_args);
-    match res {
-        None => execution_res.return_values = vec![],
-        Some(vals) => {
-            let mut value_iter = vals.into_iter();
-            let mut type_iter = ret_types.into_iter();
-            let mut json_ret_vals = vec![];
-            loop {
-                let tpe = type_iter.next();
-                if let Some(t) = tpe {
-                    let mut val = value_iter.next().unwrap();
-                    val = annotate_value(val, &t, &module_resolver);
-                    json_ret_vals.push(move_value_to_json(val));
-                } else {
-                    break;
-                }
-            }
-            execution_res.return_values = json_ret_vals;
-        }
-    }
-}
-
-fn exec_func_internal(
-    storage: InMemoryLazyStorage,
-    module: ModuleId,
-    function: &IdentStr,
-    type_args: Vec<TypeTag>,
-    args: Vec<Vec<u8>>,
-) -> Option<Vec<MoveValue>> {
-    let natives = natives::aptos_natives(
-        NativeGasParameters::zeros(),
-        AbstractValueSizeGasParameters::zeros(),
-        LATEST_GAS_FEATURE_VERSION,
-    );
-
-    let vm = MoveVM::new(natives).unwrap();
-
-    let mut extensions = NativeContextExtensions::default();
-    extensions.add(NativeTableContext::new([0u8; 32], &storage));
-    let (mut session, mut gas_status) = {
-        let gas_status = get_gas_status(
-            &move_vm_test_utils::gas_schedule::INITIAL_COST_SCHEDULE,
-            Some(1000000),
+    let file = File::create(file_path.clone()).unwrap();
+    if capture {
+        CaptureLogger::init(
+            Some(WriteLogger::new(level, Config::default(), file)),
+            level,
         )
         .unwrap();
-        let session = vm.new_session_with_extensions(&storage, extensions);
-        (session, gas_status)
-    };
-    let res = session.execute_function_bypass_visibility(
-        &module,
-        function,
-        type_args,
-        args,
-        &mut gas_status,
-    );
-    match res {
-        Ok(success_result) => {
-            let move_values: Vec<MoveValue> = success_result
-                .return_values
-                .clone()
-                .into_iter()
-                .map(|v| {
-                    let deserialized_value = MoveValue::simple_deserialize(&*v.0, &v.1).unwrap();
-                    deserialized_value
-                })
-                .collect();
-            return Some(move_values);
-        }
-        Err(err) => {
-            panic!("Error while executing the function! {}", err.to_string())
-        }
-    }
-}
-
-fn get_gas_status(cost_table: &CostTable, gas_budget: Option<u64>) -> Result<GasStatus> {
-    let gas_status = if let Some(gas_budget) = gas_budget {
-        // TODO(Gas): This should not be hardcoded.
-        let max_gas_budget = u64::MAX.checked_div(1000).unwrap();
-        if gas_budget >= max_gas_budget {
-            panic!("Gas budget set too high; maximum is {}", max_gas_budget)
-        }
-        GasStatus::new(cost_table, Gas::new(gas_budget))
     } else {
-        // no budget specified. Disable gas metering
-        GasStatus::new_unmetered()
-    };
-    Ok(gas_status)
-}
-
-#[cfg(test)]
-mod tests {
-    use crate::converter::move_value_to_json;
-    use crate::{
-        exec_func, exec_func_internal, get_node_url, ConfigData, ExecutionResult,
-        InMemoryLazyStorage, Network, ToolConfig,
-    };
-    use aptos_sdk::rest_client::Client;
-    use log::{debug, LevelFilter};
-    use move_core_types::account_address::AccountAddress;
-    use move_core_types::identifier::{IdentStr, Identifier};
-    use move_core_types::language_storage::{ModuleId, TypeTag};
-    use move_core_types::value::MoveValue;
-    use once_cell::sync::Lazy;
-    use serde_json::Value;
-    use simplelog::{Config, SimpleLogger};
-
-    static CONFIG: Lazy<ToolConfig> = Lazy::new(|| ConfigData::default().config);
-
-    #[cfg(test)]
-    #[ctor::ctor]
-    fn init() {
-        SimpleLogger::init(LevelFilter::Debug, Config::default()).unwrap();
-    }
-
-    #[test]
-    fn test_call_aptos_function_vault_e2e() {
-        let mut execution_result = ExecutionResult {
-            log_path: String::new(),
-            return_values: vec![],
-        };
-        exec_func(
-            String::from("0xeaa6ac31312d55907f6c9d7a66432d92d4da3aeef7ceb4e6242a8414ac67fa82::vault::account_collateral_and_debt"),
-            Some(vec![String::from("0x1::aptos_coin::AptosCoin")]),
-            Some(vec![String::from("0xf485fdf431d489c7bd0b83efa2413a6701fe4985d3e64a299a1a2e9fb46bcb82")]),
-        0,
-            &Network::Testnet,
-            &CONFIG,
-            &mut execution_result);
-        assert_eq!(execution_result.return_values.len(), 2);
-        debug!("{}", execution_result.return_values[0]);
-        debug!("{}", execution_result.return_values[1]);
-    }
-
-    #[test]
-    fn test_get_current_block_height() {
-        let mut execution_result = ExecutionResult {
-            log_path: String::new(),
-            return_values: vec![],
-        };
-        exec_func(
-            String::from("0x1::block::get_current_block_height"),
-            None,
-            None,
-            0,
-            &Network::Mainnet,
-            &CONFIG,
-            &mut execution_result,
-        );
-        assert_eq!(execution_result.return_values.len(), 1);
-        debug!("{}", execution_result.return_values[0]);
-    }
-
-    #[test]
-    fn test_aptos_native_function() {
-        let mut execution_result = ExecutionResult {
-            log_path: String::new(),
-            return_values: vec![],
-        };
-        exec_func(
-            String::from("0x193fbac5485237942de26fe360764e812b71a6b4f5ce8f374d41e3f55dcf01df::order::get_user_orders_history"),
-            None,
-            Some(vec![String::from("0x193fbac5485237942de26fe360764e812b71a6b4f5ce8f374d41e3f55dcf01df")]),
-            0,
-            &Network::Testnet,
-            &CONFIG,
-            &mut execution_result,
-        );
-        assert_eq!(execution_result.return_values.len(), 1);
-        debug!("{}", execution_result.return_values[0]);
-    }
-
-    #[test]
-    fn test_account_deposit() {
-        let mut execution_result = ExecutionResult {
-            log_path: String::new(),
-            return_values: vec![],
-        };
-        exec_func(
-            String::from("0xa46f37ead5670b6862709a0f17f7464a767877cba7c3c18196bc8e1e0f3c3a89::stability_pool::account_deposit"),
-            None,
-            Some(vec![String::from("0xa0fc6038965061835c42e8b8b0528841d492d3fb8f6d9e2105c613652ba9f5ce")]),
-            375991494,
-            &Network::Testnet,
-            &CONFIG,
-            &mut execution_result,
-        );
-        assert_eq!(execution_result.return_values.len(), 1);
-        assert_eq!(
-            execution_result.return_values[0],
-            serde_json::to_value("0").unwrap()
-        );
-        debug!("{}", execution_result.return_values[0]);
+        WriteLogger::init(level, Config::default(), file).unwrap();
     }
+    file_path
 }
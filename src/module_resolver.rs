@@ -1,139 +1,290 @@
+use crate::error::ComposerError;
+use crate::state_provider::StateProvider;
 use crate::types::Network;
-use anyhow::{anyhow, Result};
 use aptos_sdk::rest_client::aptos_api_types::MoveModule;
-use aptos_sdk::rest_client::{Client, MoveModuleBytecode};
+use aptos_sdk::rest_client::MoveModuleBytecode;
 use log::{debug, warn};
+use lru::LruCache;
+use move_binary_format::CompiledModule;
 use move_core_types::account_address::AccountAddress;
-use move_core_types::identifier::Identifier;
 use move_core_types::language_storage::ModuleId;
-use parking_lot::RwLock;
-use std::borrow::Borrow;
-use std::collections::HashMap;
-use std::path::Path;
-use std::str::FromStr;
-use tokio::runtime::Runtime;
+use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+type ModuleCacheKey = (ModuleId, u64);
+type ModuleCacheEntry = (Option<Vec<u8>>, Option<MoveModule>);
+
+// Bounds of the memory caches, a long-running server sees a new ledger version on every call
+const MODULE_CACHE_SIZE: usize = 1024;
+const VERSION_CACHE_SIZE: usize = 16 * 1024;
+// Bound of the disk cache in modules, it starts over once it's full
+const DISK_CACHE_SIZE: usize = 16 * 1024;
+// How long a module read at the latest ledger version is assumed unchanged at the following
+// latest versions, before it's read again to catch an upgrade
+const LATEST_MODULE_TTL: Duration = Duration::from_secs(10);
+
+/// The ledger versions between two reads of a module which returned the same bytecode.
+struct ModuleSpan {
+    first: u64,
+    last: u64,
+    entry: Arc<ModuleCacheEntry>,
+    // When the module was last read at the latest ledger version
+    latest_read_at: Option<Instant>,
+}
+
+/// Resolves modules with their ABI, caching them in memory and, for the cached accounts, on disk.
+/// Clones share the same memory cache, so the storage of an execution and the conversion of its
+/// results load a module once between them.
+#[derive(Clone)]
 pub struct CacheModuleResolver {
     network: Network,
-    client: Client,
-    cache_folder: String,
-    module_cache: RwLock<HashMap<ModuleId, (Option<Vec<u8>>, Option<MoveModule>)>>,
+    provider: Arc<dyn StateProvider>,
+    // `None` disables the disk cache
+    cache_folder: Option<String>,
+    // The spans of each module sorted by version, so the calls at different ledger versions share
+    // a module as long as it isn't upgraded
+    modules: Arc<Mutex<LruCache<ModuleId, Vec<ModuleSpan>>>>,
+    // The module at each ledger version. The cell is filled by the first lookup, concurrent
+    // lookups wait for it
+    versions: Arc<Mutex<LruCache<ModuleCacheKey, Arc<OnceCell<Arc<ModuleCacheEntry>>>>>>,
+    // The latest ledger version pinned by the calls, `0` if none
+    latest_version: Arc<AtomicU64>,
+    // The number of modules in the disk cache, counted on the first write
+    disk_cache_size: Arc<OnceCell<AtomicUsize>>,
+    disk_cache_limit: usize,
     enable_module_caching: bool,
 }
 
-impl Clone for CacheModuleResolver {
-    fn clone(&self) -> Self {
-        Self {
-            network: self.network,
-            client: self.client.clone(),
-            cache_folder: self.cache_folder.clone(),
-            module_cache: RwLock::new(self.module_cache.read().clone()),
-            enable_module_caching: self.enable_module_caching,
-        }
-    }
-}
-
 impl CacheModuleResolver {
     pub fn new(
-        network: &Network,
-        client: Client,
-        cache_folder: String,
+        network: Network,
+        provider: Arc<dyn StateProvider>,
+        cache_folder: Option<String>,
         enable_module_caching: bool,
     ) -> Self {
         Self {
-            network: *network,
-            client,
+            network,
+            provider,
             cache_folder,
-            module_cache: RwLock::new(HashMap::new()),
+            modules: Arc::new(Mutex::new(LruCache::new(
+                NonZeroUsize::new(MODULE_CACHE_SIZE).unwrap(),
+            ))),
+            versions: Arc::new(Mutex::new(LruCache::new(
+                NonZeroUsize::new(VERSION_CACHE_SIZE).unwrap(),
+            ))),
+            latest_version: Arc::new(AtomicU64::new(0)),
+            disk_cache_size: Arc::new(OnceCell::new()),
+            disk_cache_limit: DISK_CACHE_SIZE,
             enable_module_caching,
         }
     }
 
+    /// Records the ledger version a call pinned as the latest one. The modules read at the latest
+    /// version are reused by the following latest versions for a few seconds, and never written
+    /// to disk.
+    pub fn set_latest_version(&self, ledger_version: u64) {
+        self.latest_version
+            .fetch_max(ledger_version, Ordering::SeqCst);
+    }
+
+    /// Returns the bytecode and the ABI of the module as it was at `ledger_version`.
     pub fn get_module(
         &self,
         module_id: &ModuleId,
-    ) -> Result<(Option<Vec<u8>>, Option<MoveModule>)> {
-        let locked_cache = self.module_cache.read();
-        if let Some(res) = locked_cache.get(module_id) {
+        ledger_version: u64,
+    ) -> Result<ModuleCacheEntry, ComposerError> {
+        let cell = self.get_cache_cell(&(module_id.clone(), ledger_version));
+        if cell.get().is_some() {
             debug!("loading module {} from memory cache", module_id);
-            return match res {
-                (Some(bytecode), Some(abi)) => Ok((Some(bytecode.clone()), Some(abi.clone()))),
-                _ => Ok((None, None)),
-            };
         }
-        drop(locked_cache);
-        let addr = module_id.address();
+        // A failed load leaves the cell empty, so the next lookup tries again
+        match cell
+            .get_or_try_init(|| self.load_module(module_id, ledger_version))?
+            .as_ref()
+        {
+            (Some(bytecode), Some(abi)) => Ok((Some(bytecode.clone()), Some(abi.clone()))),
+            _ => Ok((None, None)),
+        }
+    }
+
+    fn get_cache_cell(&self, key: &ModuleCacheKey) -> Arc<OnceCell<Arc<ModuleCacheEntry>>> {
+        let mut versions = self.versions.lock();
+        if let Some(cell) = versions.get(key) {
+            return cell.clone();
+        }
+        let cell = Arc::new(OnceCell::new());
+        versions.put(key.clone(), cell.clone());
+        cell
+    }
+
+    fn load_module(
+        &self,
+        module_id: &ModuleId,
+        ledger_version: u64,
+    ) -> Result<Arc<ModuleCacheEntry>, ComposerError> {
+        if let Some(entry) = self.find_module(module_id, ledger_version) {
+            debug!("reusing module {} at {}", module_id, ledger_version);
+            return Ok(entry);
+        }
         // Get module from the local cache if:
-        // 1. enable the caching 2. it belongs to standard module
-        if self.is_cached_module(addr) {
-            if let Some(res) = self.try_load_module_from_disk_cache(module_id) {
-                return Ok(res);
+        // 1. enable the caching 2. it belongs to standard module 3. the version is historical
+        // The disk cache is keyed by the ledger version, so an upgraded package never serves the
+        // bytecode of a former upgrade.
+        let is_latest = self.is_latest_version(ledger_version);
+        let is_cached_module = !is_latest && self.is_cached_module(module_id.address());
+        if is_cached_module {
+            if let Some(bytecode) = self.try_load_module_from_disk_cache(module_id, ledger_version)
+            {
+                match self.add_module(module_id, ledger_version, Some(bytecode), false) {
+                    Ok(entry) => return Ok(entry),
+                    // Read from the provider instead, which overwrites the entry
+                    Err(e) => warn!("Invalid module {} in the disk cache: {}", module_id, e),
+                }
             }
         }
-        use aptos_sdk::move_types::account_address::AccountAddress as AptosAccountAddress;
-        let aptos_account = AptosAccountAddress::from_bytes(addr.into_bytes()).unwrap();
-        let mut abi: Option<MoveModule> = None;
-        let matched_module = Runtime::new()
-            .unwrap()
-            .block_on(self.client.get_account_modules(aptos_account))
-            .unwrap()
-            .into_inner()
-            .into_iter()
-            .find(|module| {
-                if let Ok(mod_) =
-                    MoveModuleBytecode::new(module.bytecode.0.to_vec()).try_parse_abi()
-                {
-                    abi = mod_.abi.clone();
-                    let module_address = abi.as_ref().unwrap().address.to_string();
-                    let module_name = abi.as_ref().unwrap().name.as_str();
-                    let inner_module_id = &ModuleId::new(
-                        AccountAddress::from_str(module_address.as_str()).unwrap(),
-                        Identifier::from_str(module_name).unwrap(),
-                    );
-                    // caching the module into memory before head
-                    let mut locked_cache = self.module_cache.write();
-                    locked_cache.insert(
-                        inner_module_id.clone(),
-                        (Some(module.bytecode.0.clone()), abi.clone()),
-                    );
-                    drop(locked_cache);
-
-                    // caching the standard module to disk
-                    if self.is_cached_module(addr) {
-                        self.write_module_cache_to_disk(inner_module_id, module.bytecode.0.clone());
-                    }
-                    return module_name == module_id.name().as_str();
-                }
-                false
-            });
-        if let Some(module) = matched_module {
-            debug!("load module: {}::{}", addr, module_id.name().as_str());
-            return Ok((Option::from(module.bytecode.0.clone()), abi));
+        let bytecode = self.provider.get_module(module_id, ledger_version)?;
+        match &bytecode {
+            // caching the standard module to disk
+            Some(bytecode) if is_cached_module => {
+                self.write_module_cache_to_disk(module_id, ledger_version, bytecode.clone())
+            }
+            _ => {}
+        }
+        self.add_module(module_id, ledger_version, bytecode, is_latest)
+    }
+
+    fn is_latest_version(&self, ledger_version: u64) -> bool {
+        let latest_version = self.latest_version.load(Ordering::SeqCst);
+        latest_version > 0 && ledger_version >= latest_version
+    }
+
+    // The module at `ledger_version` if it was read with the same bytecode before and after it,
+    // or if it was read at the latest version a moment ago
+    fn find_module(
+        &self,
+        module_id: &ModuleId,
+        ledger_version: u64,
+    ) -> Option<Arc<ModuleCacheEntry>> {
+        let mut modules = self.modules.lock();
+        let spans = modules.get_mut(module_id)?;
+        if let Some(span) = spans
+            .iter()
+            .find(|span| span.first <= ledger_version && ledger_version <= span.last)
+        {
+            return Some(span.entry.clone());
+        }
+        let span = spans.last_mut()?;
+        let is_recent = span
+            .latest_read_at
+            .map_or(false, |read_at| read_at.elapsed() < LATEST_MODULE_TTL);
+        if ledger_version > span.last && is_recent {
+            span.last = ledger_version;
+            return Some(span.entry.clone());
+        }
+        None
+    }
+
+    /// Adds the module read at `ledger_version`. Its ABI is parsed unless the module was read with
+    /// the same bytecode at the closest versions before or after.
+    fn add_module(
+        &self,
+        module_id: &ModuleId,
+        ledger_version: u64,
+        bytecode: Option<Vec<u8>>,
+        is_latest: bool,
+    ) -> Result<Arc<ModuleCacheEntry>, ComposerError> {
+        let latest_read_at = if is_latest {
+            Some(Instant::now())
+        } else {
+            None
+        };
+        let mut modules = self.modules.lock();
+        let mut spans = modules.pop(module_id).unwrap_or_default();
+        let next = spans.partition_point(|span| span.first <= ledger_version);
+        let neighbours = next.saturating_sub(1)..(next + 1).min(spans.len());
+        if let Some(span) = spans[neighbours]
+            .iter_mut()
+            .find(|span| span.entry.0 == bytecode)
+        {
+            span.first = span.first.min(ledger_version);
+            span.last = span.last.max(ledger_version);
+            span.latest_read_at = latest_read_at.or(span.latest_read_at);
+            let entry = span.entry.clone();
+            modules.put(module_id.clone(), spans);
+            return Ok(entry);
         }
-        Ok((None, abi))
+        // Parsed without holding the lock, the other modules can be read meanwhile
+        modules.put(module_id.clone(), spans);
+        drop(modules);
+        let entry = Arc::new(match bytecode {
+            Some(bytecode) => {
+                let abi = parse_abi(bytecode.clone())?;
+                (Some(bytecode), abi)
+            }
+            None => (None, None),
+        });
+        let mut modules = self.modules.lock();
+        let mut spans = modules.pop(module_id).unwrap_or_default();
+        let next = spans.partition_point(|span| span.first <= ledger_version);
+        spans.insert(
+            next,
+            ModuleSpan {
+                first: ledger_version,
+                last: ledger_version,
+                entry: entry.clone(),
+                latest_read_at,
+            },
+        );
+        modules.put(module_id.clone(), spans);
+        Ok(entry)
+    }
+
+    /// Loads all the modules under `addr` at `ledger_version` in one go, instead of one by one as
+    /// they're looked up. It pays off when most of the modules of an account are used, e.g. a
+    /// function whose dependencies are published along with it.
+    pub fn prefetch_modules(
+        &self,
+        addr: &AccountAddress,
+        ledger_version: u64,
+    ) -> Result<(), ComposerError> {
+        let modules = self.provider.get_account_modules(addr, ledger_version)?;
+        debug!("prefetched {} modules under {}", modules.len(), addr);
+        let is_latest = self.is_latest_version(ledger_version);
+        for bytecode in modules {
+            let module_id = CompiledModule::deserialize(&bytecode)
+                .map_err(|e| ComposerError::Conversion(e.to_string()))?
+                .self_id();
+            let entry = self.add_module(&module_id, ledger_version, Some(bytecode), is_latest)?;
+            // Keeps the entry of a module which is already loaded
+            let _ = self.get_cache_cell(&(module_id, ledger_version)).set(entry);
+        }
+        Ok(())
     }
 
     fn is_cached_module(&self, addr: &AccountAddress) -> bool {
-        self.enable_module_caching
-            || addr.to_hex_literal() == "0x1"
-            || addr.to_hex_literal() == "0x3"
+        self.cache_folder.is_some()
+            && (self.enable_module_caching
+                || addr.to_hex_literal() == "0x1"
+                || addr.to_hex_literal() == "0x3")
     }
 
     fn try_load_module_from_disk_cache(
         &self,
         module_id: &ModuleId,
-    ) -> Option<(Option<Vec<u8>>, Option<MoveModule>)> {
-        let module_cache_key = self.get_cache_key(module_id);
-        let cache_path = self.get_cache_path(self.cache_folder.clone());
+        ledger_version: u64,
+    ) -> Option<Vec<u8>> {
+        let module_cache_key = self.get_cache_key(module_id, ledger_version);
+        let cache_path = self.get_cache_path()?;
         let cached_module = cacache::read_sync(cache_path, module_cache_key);
         match cached_module {
             Ok(m) => {
                 debug!("loaded module from disk cache: {}", module_id);
-                let _abi = MoveModuleBytecode::new(m.clone())
-                    .try_parse_abi()
-                    .unwrap()
-                    .abi;
-                return Some((Some(m), _abi));
+                return Some(m);
             }
             Err(e) => {
                 warn!("{}", e);
@@ -142,27 +293,330 @@ impl CacheModuleResolver {
         None
     }
 
-    fn write_module_cache_to_disk(&self, module_id: &ModuleId, bytecode: Vec<u8>) {
+    fn write_module_cache_to_disk(
+        &self,
+        module_id: &ModuleId,
+        ledger_version: u64,
+        bytecode: Vec<u8>,
+    ) {
         debug!("Caching {} to disk", module_id);
-        cacache::write_sync(
-            self.get_cache_path(self.cache_folder.clone()),
-            self.get_cache_key(module_id),
+        let cache_path = match self.get_cache_path() {
+            Some(cache_path) => cache_path,
+            None => return,
+        };
+        // Every historical version adds entries, so the cache is cleared once it's full
+        let size = self.disk_cache_size.get_or_init(|| {
+            let modules = cacache::list_sync(&cache_path).flatten().count();
+            AtomicUsize::new(modules)
+        });
+        if size.fetch_add(1, Ordering::SeqCst) >= self.disk_cache_limit {
+            debug!("Clearing the full disk cache");
+            if let Err(e) = cacache::clear_sync(&cache_path) {
+                warn!("Failed to clear the disk cache: {}", e);
+            }
+            size.store(1, Ordering::SeqCst);
+        }
+        if let Err(e) = cacache::write_sync(
+            cache_path,
+            self.get_cache_key(module_id, ledger_version),
             bytecode,
+        ) {
+            warn!("Failed to cache the module {}: {}", module_id, e);
+        }
+    }
+
+    // Namespaced by the network name, as the same module id can differ across networks
+    fn get_cache_key(&self, module_id: &ModuleId, ledger_version: u64) -> String {
+        format!(
+            "{}/{}@{}",
+            self.network,
+            module_id.short_str_lossless(),
+            ledger_version
         )
-        .expect("Failed to cache the module");
     }
 
-    fn get_cache_key(&self, module_id: &ModuleId) -> String {
-        let mut module_cache_key = module_id.to_string();
-        module_cache_key.push_str(self.network.as_str());
-        module_cache_key
+    fn get_cache_path(&self) -> Option<PathBuf> {
+        let cache_folder = self.cache_folder.as_ref()?;
+        Some(Path::new(cache_folder).join(".move-modules-cache"))
     }
+}
+
+fn parse_abi(bytecode: Vec<u8>) -> Result<Option<MoveModule>, ComposerError> {
+    Ok(MoveModuleBytecode::new(bytecode)
+        .try_parse_abi()
+        .map_err(|e| ComposerError::Conversion(e.to_string()))?
+        .abi)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::ComposerError;
+    use crate::module_resolver::{CacheModuleResolver, LATEST_MODULE_TTL};
+    use crate::state_provider::StateProvider;
+    use crate::test_utils::module_bytecode;
+    use crate::types::Network;
+    use move_binary_format::file_format::Bytecode;
+    use move_core_types::account_address::AccountAddress;
+    use move_core_types::identifier::Identifier;
+    use move_core_types::language_storage::{ModuleId, StructTag, CORE_CODE_ADDRESS};
+    use move_table_extension::TableHandle;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use uuid::Uuid;
+
+    /// Serves `0x1::counter`, upgraded at the ledger version 20, and counts the module reads. The
+    /// first `failures` reads fail.
+    #[derive(Default)]
+    struct UpgradedModuleProvider {
+        module_reads: AtomicUsize,
+        failures: AtomicUsize,
+    }
+
+    impl StateProvider for UpgradedModuleProvider {
+        fn latest_version(&self) -> Result<u64, ComposerError> {
+            Ok(30)
+        }
+
+        fn chain_id(&self) -> Result<u8, ComposerError> {
+            Ok(4)
+        }
+
+        fn get_module(
+            &self,
+            _module_id: &ModuleId,
+            ledger_version: u64,
+        ) -> Result<Option<Vec<u8>>, ComposerError> {
+            self.module_reads.fetch_add(1, Ordering::SeqCst);
+            // Slow enough for the concurrent lookups to overlap
+            thread::sleep(Duration::from_millis(50));
+            if self
+                .failures
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                .is_ok()
+            {
+                return Err(ComposerError::Network(String::from("connection reset")));
+            }
+            let upgrade = if ledger_version < 20 { "v0" } else { "v1" };
+            Ok(Some(counter_bytecode(upgrade)))
+        }
+
+        fn get_resource(
+            &self,
+            _address: &AccountAddress,
+            _tag: &StructTag,
+            _ledger_version: u64,
+        ) -> Result<Option<Vec<u8>>, ComposerError> {
+            Ok(None)
+        }
+
+        fn get_table_item(
+            &self,
+            _handle: &TableHandle,
+            _key: &[u8],
+            _ledger_version: u64,
+        ) -> Result<Option<Vec<u8>>, ComposerError> {
+            Ok(None)
+        }
+    }
+
+    // The upgrades of the module differ by the name of their function
+    fn counter_bytecode(upgrade: &'static str) -> Vec<u8> {
+        module_bytecode(
+            "counter",
+            vec![(upgrade, vec![], vec![], vec![Bytecode::Ret])],
+        )
+    }
+
+    fn cache_folder() -> String {
+        std::env::temp_dir()
+            .join(format!("modules_{}", Uuid::new_v4()))
+            .display()
+            .to_string()
+    }
+
+    fn counter() -> ModuleId {
+        ModuleId::new(CORE_CODE_ADDRESS, Identifier::new("counter").unwrap())
+    }
+
+    fn spans(module_resolver: &CacheModuleResolver) -> Vec<(u64, u64)> {
+        module_resolver
+            .modules
+            .lock()
+            .peek(&counter())
+            .unwrap()
+            .iter()
+            .map(|span| (span.first, span.last))
+            .collect()
+    }
+
+    #[test]
+    fn test_share_modules_across_versions() {
+        let provider = Arc::new(UpgradedModuleProvider::default());
+        let module_resolver =
+            CacheModuleResolver::new(Network::Localnet, provider.clone(), None, false);
+
+        let (bytecode, abi) = module_resolver.get_module(&counter(), 10).unwrap();
+        assert_eq!(bytecode, Some(counter_bytecode("v0")));
+        assert_eq!(abi.unwrap().name.to_string(), "counter");
+        module_resolver.get_module(&counter(), 15).unwrap();
+        // The versions between two reads of the same bytecode share the module
+        for ledger_version in [10, 12, 15] {
+            let (bytecode, _) = module_resolver
+                .get_module(&counter(), ledger_version)
+                .unwrap();
+            assert_eq!(bytecode, Some(counter_bytecode("v0")));
+        }
+        assert_eq!(provider.module_reads.load(Ordering::SeqCst), 2);
+        assert_eq!(spans(&module_resolver), vec![(10, 15)]);
+
+        let (bytecode, _) = module_resolver.get_module(&counter(), 20).unwrap();
+        assert_eq!(bytecode, Some(counter_bytecode("v1")));
+        module_resolver.get_module(&counter(), 17).unwrap();
+        module_resolver.get_module(&counter(), 16).unwrap();
+        assert_eq!(provider.module_reads.load(Ordering::SeqCst), 4);
+        assert_eq!(spans(&module_resolver), vec![(10, 17), (20, 20)]);
+    }
+
+    #[test]
+    fn test_reuse_modules_at_latest_versions() {
+        let provider = Arc::new(UpgradedModuleProvider::default());
+        let module_resolver =
+            CacheModuleResolver::new(Network::Localnet, provider.clone(), None, false);
+
+        module_resolver.set_latest_version(20);
+        module_resolver.get_module(&counter(), 20).unwrap();
+        module_resolver.set_latest_version(25);
+        let (bytecode, _) = module_resolver.get_module(&counter(), 25).unwrap();
+        assert_eq!(bytecode, Some(counter_bytecode("v1")));
+        module_resolver.get_module(&counter(), 22).unwrap();
+        assert_eq!(provider.module_reads.load(Ordering::SeqCst), 1);
+        assert_eq!(spans(&module_resolver), vec![(20, 25)]);
+
+        // Read again once it's too old to be trusted
+        module_resolver.modules.lock().get_mut(&counter()).unwrap()[0].latest_read_at =
+            Instant::now().checked_sub(LATEST_MODULE_TTL);
+        module_resolver.set_latest_version(30);
+        module_resolver.get_module(&counter(), 30).unwrap();
+        assert_eq!(provider.module_reads.load(Ordering::SeqCst), 2);
+        assert_eq!(spans(&module_resolver), vec![(20, 30)]);
+        // A historical version is never guessed
+        module_resolver.get_module(&counter(), 19).unwrap();
+        assert_eq!(provider.module_reads.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_disk_cache_historical_versions() {
+        let provider = Arc::new(UpgradedModuleProvider::default());
+        let cache_folder = cache_folder();
+        let mut module_resolver = CacheModuleResolver::new(
+            Network::Localnet,
+            provider,
+            Some(cache_folder.clone()),
+            false,
+        );
+        module_resolver.disk_cache_limit = 2;
+        let cache_path = module_resolver.get_cache_path().unwrap();
+        let disk_modules = || cacache::list_sync(&cache_path).flatten().count();
+
+        module_resolver.get_module(&counter(), 10).unwrap();
+        module_resolver.get_module(&counter(), 15).unwrap();
+        assert_eq!(disk_modules(), 2);
+        // The latest versions are only kept in memory
+        module_resolver.set_latest_version(25);
+        module_resolver.get_module(&counter(), 25).unwrap();
+        assert_eq!(disk_modules(), 2);
+        // A full cache starts over
+        module_resolver.get_module(&counter(), 20).unwrap();
+        assert_eq!(disk_modules(), 1);
+        assert_eq!(
+            module_resolver.try_load_module_from_disk_cache(&counter(), 20),
+            Some(counter_bytecode("v1"))
+        );
+        std::fs::remove_dir_all(&cache_folder).unwrap();
+    }
+
+    #[test]
+    fn test_load_module_once() {
+        let provider = Arc::new(UpgradedModuleProvider::default());
+        let module_resolver =
+            CacheModuleResolver::new(Network::Localnet, provider.clone(), None, false);
+
+        let barrier = Arc::new(Barrier::new(4));
+        let handles = (0..4)
+            .map(|_| {
+                let module_resolver = module_resolver.clone();
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    barrier.wait();
+                    module_resolver.get_module(&counter(), 10).unwrap()
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            let (bytecode, _) = handle.join().unwrap();
+            assert_eq!(bytecode, Some(counter_bytecode("v0")));
+        }
+        assert_eq!(provider.module_reads.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_retry_failed_module_load() {
+        let provider = Arc::new(UpgradedModuleProvider::default());
+        provider.failures.store(1, Ordering::SeqCst);
+        let module_resolver =
+            CacheModuleResolver::new(Network::Localnet, provider.clone(), None, false);
+
+        assert!(matches!(
+            module_resolver.get_module(&counter(), 10),
+            Err(ComposerError::Network(_))
+        ));
+        // The failure isn't cached
+        let (bytecode, _) = module_resolver.get_module(&counter(), 10).unwrap();
+        assert_eq!(bytecode, Some(counter_bytecode("v0")));
+        module_resolver.get_module(&counter(), 10).unwrap();
+        assert_eq!(provider.module_reads.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_fall_back_on_disk_cache_failures() {
+        let provider = Arc::new(UpgradedModuleProvider::default());
+        let cache_folder = cache_folder();
+        let module_resolver = CacheModuleResolver::new(
+            Network::Localnet,
+            provider.clone(),
+            Some(cache_folder.clone()),
+            false,
+        );
+        let cache_path = module_resolver.get_cache_path().unwrap();
+        cacache::write_sync(
+            &cache_path,
+            module_resolver.get_cache_key(&counter(), 10),
+            b"corrupt",
+        )
+        .unwrap();
+
+        let (bytecode, _) = module_resolver.get_module(&counter(), 10).unwrap();
+        assert_eq!(bytecode, Some(counter_bytecode("v0")));
+        assert_eq!(provider.module_reads.load(Ordering::SeqCst), 1);
+        // The entry is fixed by the read from the provider
+        assert_eq!(
+            module_resolver.try_load_module_from_disk_cache(&counter(), 10),
+            Some(counter_bytecode("v0"))
+        );
+        std::fs::remove_dir_all(&cache_folder).unwrap();
 
-    fn get_cache_path(&self, cache_folder: String) -> String {
-        return Path::new(&cache_folder)
-            .join(".move-modules-cache")
-            .into_os_string()
-            .into_string()
-            .unwrap();
+        // A cache folder which can't be written only leaves the module out of the disk cache
+        std::fs::write(&cache_folder, b"").unwrap();
+        let module_resolver = CacheModuleResolver::new(
+            Network::Localnet,
+            provider,
+            Some(cache_folder.clone()),
+            false,
+        );
+        let (bytecode, _) = module_resolver.get_module(&counter(), 20).unwrap();
+        assert_eq!(bytecode, Some(counter_bytecode("v1")));
+        std::fs::remove_file(&cache_folder).unwrap();
     }
 }
@@ -0,0 +1,98 @@
+use std::collections::BTreeMap;
+
+use aptos_gas::{
+    AptosGasParameters, FromOnChainGasSchedule, InitialGasSchedule, LATEST_GAS_FEATURE_VERSION,
+};
+use log::{debug, warn};
+use move_core_types::identifier::Identifier;
+use move_core_types::language_storage::{StructTag, CORE_CODE_ADDRESS};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::error::ComposerError;
+use crate::state_provider::StateProvider;
+
+/// Mirrors `0x1::gas_schedule::GasScheduleV2`.
+#[derive(Deserialize)]
+struct GasScheduleV2 {
+    feature_version: u64,
+    entries: Vec<(String, u64)>,
+}
+
+/// Mirrors `0x1::gas_schedule::GasSchedule`, the schedule before `GasScheduleV2`.
+#[derive(Deserialize)]
+struct GasSchedule {
+    entries: Vec<(String, u64)>,
+}
+
+/// Configuration of the chain at a ledger version, which the natives and the gas meter are built
+/// from, so historical calls are charged the way the chain charged them. The feature flags need
+/// no configuration, the framework reads `0x1::features::Features` from the storage, which serves
+/// it at the same ledger version.
+pub struct OnChainConfig {
+    pub gas_feature_version: u64,
+    pub gas_params: AptosGasParameters,
+}
+
+impl OnChainConfig {
+    /// Loads the gas schedule at `ledger_version`. The gas parameters of this build are used when
+    /// the chain has no schedule this build can read.
+    pub fn load(provider: &dyn StateProvider, ledger_version: u64) -> Result<Self, ComposerError> {
+        let (gas_feature_version, entries) = match get_config::<GasScheduleV2>(
+            provider,
+            "gas_schedule",
+            "GasScheduleV2",
+            ledger_version,
+        )? {
+            Some(schedule) => (schedule.feature_version, Some(schedule.entries)),
+            None => (
+                0,
+                get_config::<GasSchedule>(provider, "gas_schedule", "GasSchedule", ledger_version)?
+                    .map(|schedule| schedule.entries),
+            ),
+        };
+        let gas_params = entries.and_then(|entries| {
+            AptosGasParameters::from_on_chain_gas_schedule(
+                &entries.into_iter().collect::<BTreeMap<_, _>>(),
+            )
+        });
+        let (gas_feature_version, gas_params) = match gas_params {
+            Some(gas_params) => (gas_feature_version, gas_params),
+            None => {
+                warn!(
+                    "No readable gas schedule at ledger version {}, use the built-in one",
+                    ledger_version
+                );
+                (LATEST_GAS_FEATURE_VERSION, AptosGasParameters::initial())
+            }
+        };
+        debug!(
+            "Gas feature version {} at ledger version {}",
+            gas_feature_version, ledger_version
+        );
+        Ok(Self {
+            gas_feature_version,
+            gas_params,
+        })
+    }
+}
+
+fn get_config<T: DeserializeOwned>(
+    provider: &dyn StateProvider,
+    module: &str,
+    name: &str,
+    ledger_version: u64,
+) -> Result<Option<T>, ComposerError> {
+    let tag = StructTag {
+        address: CORE_CODE_ADDRESS,
+        module: Identifier::new(module).unwrap(),
+        name: Identifier::new(name).unwrap(),
+        type_params: vec![],
+    };
+    match provider.get_resource(&CORE_CODE_ADDRESS, &tag, ledger_version)? {
+        Some(bytes) => bcs::from_bytes::<T>(&bytes)
+            .map(Some)
+            .map_err(|e| ComposerError::Conversion(format!("Invalid {}: {}", tag, e))),
+        None => Ok(None),
+    }
+}
@@ -0,0 +1,202 @@
+use std::collections::BTreeMap;
+
+use log::warn;
+use move_binary_format::CompiledModule;
+use move_core_types::language_storage::StructTag;
+use move_core_types::parser::parse_struct_tag;
+use serde::Deserialize;
+
+use crate::error::ComposerError;
+
+const METADATA_V1_KEY: &[u8] = b"aptos::metadata_v1";
+const RESOURCE_GROUP_MEMBER: u8 = 3;
+
+/// Mirrors `RuntimeModuleMetadataV1`, the metadata the Aptos compiler attaches to modules.
+#[allow(dead_code)]
+#[derive(Deserialize)]
+struct RuntimeModuleMetadataV1 {
+    error_map: BTreeMap<u64, ErrorDescription>,
+    struct_attributes: BTreeMap<String, Vec<KnownAttribute>>,
+    fun_attributes: BTreeMap<String, Vec<KnownAttribute>>,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize)]
+struct ErrorDescription {
+    code_name: String,
+    code_description: String,
+}
+
+#[derive(Deserialize)]
+struct KnownAttribute {
+    kind: u8,
+    args: Vec<String>,
+}
+
+/// Reads the resource group the struct `name` of the module belongs to, from the
+/// `#[resource_group_member(group = ...)]` attribute kept in the module metadata.
+pub fn get_resource_group(module: &[u8], name: &str) -> Option<StructTag> {
+    let module = CompiledModule::deserialize(module).ok()?;
+    let metadata = module
+        .metadata
+        .iter()
+        .find(|metadata| metadata.key == METADATA_V1_KEY)?;
+    let metadata = match bcs::from_bytes::<RuntimeModuleMetadataV1>(&metadata.value) {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            warn!("Invalid metadata of module {}: {}", module.self_id(), e);
+            return None;
+        }
+    };
+    metadata
+        .struct_attributes
+        .get(name)?
+        .iter()
+        .find(|attribute| attribute.kind == RESOURCE_GROUP_MEMBER)
+        .and_then(|attribute| attribute.args.first())
+        .and_then(|group| parse_struct_tag(group).ok())
+}
+
+/// Extracts the member `tag` out of a resource group, which is stored as one BCS encoded map from
+/// the member struct tags to the member resources.
+pub fn get_group_member(group: &[u8], tag: &StructTag) -> Result<Option<Vec<u8>>, ComposerError> {
+    let mut members = bcs::from_bytes::<BTreeMap<StructTag, Vec<u8>>>(group)
+        .map_err(|e| ComposerError::Conversion(format!("Invalid resource group: {}", e)))?;
+    Ok(members.remove(tag))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::module_resolver::CacheModuleResolver;
+    use crate::resource_group::{
+        get_group_member, get_resource_group, METADATA_V1_KEY, RESOURCE_GROUP_MEMBER,
+    };
+    use crate::state_provider::InMemoryStateProvider;
+    use crate::storage::InMemoryLazyStorage;
+    use crate::test_utils::TestModule;
+    use crate::types::Network;
+    use move_binary_format::file_format::{Ability, AbilitySet, SignatureToken};
+    use move_core_types::account_address::AccountAddress;
+    use move_core_types::identifier::Identifier;
+    use move_core_types::language_storage::{ModuleId, StructTag, CORE_CODE_ADDRESS};
+    use move_core_types::resolver::ResourceResolver;
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+
+    type Attributes = BTreeMap<String, Vec<(u8, Vec<String>)>>;
+
+    fn object_tag(name: &str) -> StructTag {
+        StructTag {
+            address: CORE_CODE_ADDRESS,
+            module: Identifier::new("object").unwrap(),
+            name: Identifier::new(name).unwrap(),
+            type_params: vec![],
+        }
+    }
+
+    // `0x1::object` whose `ObjectCore` and `Untransferable` are members of `ObjectGroup`, with the
+    // metadata encoded as the Aptos compiler writes `RuntimeModuleMetadataV1`
+    fn object_module() -> Vec<u8> {
+        let mut module = TestModule::new("object");
+        let mut struct_attributes = Attributes::new();
+        for name in ["ObjectCore", "Untransferable"] {
+            module.add_struct(
+                name,
+                AbilitySet::EMPTY | Ability::Key,
+                vec![("value", SignatureToken::U64)],
+            );
+            struct_attributes.insert(
+                String::from(name),
+                vec![(
+                    RESOURCE_GROUP_MEMBER,
+                    vec![String::from("0x1::object::ObjectGroup")],
+                )],
+            );
+        }
+        let metadata = (
+            BTreeMap::<u64, (String, String)>::new(),
+            struct_attributes,
+            Attributes::new(),
+        );
+        module.add_metadata(METADATA_V1_KEY, bcs::to_bytes(&metadata).unwrap());
+        module.bytecode()
+    }
+
+    #[test]
+    fn test_get_resource_group() {
+        let module = object_module();
+        assert_eq!(
+            get_resource_group(&module, "ObjectCore"),
+            Some(object_tag("ObjectGroup"))
+        );
+        assert_eq!(get_resource_group(&module, "ObjectGroup"), None);
+        assert_eq!(
+            get_resource_group(&TestModule::new("object").bytecode(), "ObjectCore"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_resolve_group_member() {
+        let address = AccountAddress::from_hex_literal("0x42").unwrap();
+        let mut members = BTreeMap::new();
+        members.insert(object_tag("ObjectCore"), bcs::to_bytes(&7u64).unwrap());
+        let mut state = InMemoryStateProvider::new(4, 10);
+        state.add_module(
+            ModuleId::new(CORE_CODE_ADDRESS, Identifier::new("object").unwrap()),
+            object_module(),
+        );
+        state.add_resource(
+            address,
+            object_tag("ObjectGroup"),
+            bcs::to_bytes(&members).unwrap(),
+        );
+        let provider = Arc::new(state);
+        let module_resolver =
+            CacheModuleResolver::new(Network::Localnet, provider.clone(), None, false);
+        let storage = InMemoryLazyStorage::new(10, provider, module_resolver);
+
+        assert_eq!(
+            storage
+                .get_resource(&address, &object_tag("ObjectCore"))
+                .unwrap(),
+            Some(bcs::to_bytes(&7u64).unwrap())
+        );
+        // A member missing from the group, and a group missing from the account
+        assert_eq!(
+            storage
+                .get_resource(&address, &object_tag("Untransferable"))
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            storage
+                .get_resource(&CORE_CODE_ADDRESS, &object_tag("ObjectCore"))
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_get_group_member() {
+        let tag = |name: &str| StructTag {
+            address: CORE_CODE_ADDRESS,
+            module: Identifier::new("object").unwrap(),
+            name: Identifier::new(name).unwrap(),
+            type_params: vec![],
+        };
+        let mut members = BTreeMap::new();
+        members.insert(tag("ObjectCore"), vec![1, 2, 3]);
+        let group = bcs::to_bytes(&members).unwrap();
+
+        assert_eq!(
+            get_group_member(&group, &tag("ObjectCore")).unwrap(),
+            Some(vec![1, 2, 3])
+        );
+        assert_eq!(
+            get_group_member(&group, &tag("Untransferable")).unwrap(),
+            None
+        );
+        assert!(get_group_member(&[1], &tag("ObjectCore")).is_err());
+    }
+}
@@ -0,0 +1,429 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use log::{debug, error, info};
+use parking_lot::{Mutex, RwLock};
+use poem::endpoint::StaticFilesEndpoint;
+use poem::http::StatusCode;
+use poem::listener::TcpListener;
+use poem::middleware::Cors;
+use poem::web::{Data, Json};
+use poem::{get, handler, post, Endpoint, EndpointExt, IntoResponse, Request, Response, Route};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::composer::Composer;
+use crate::config::ToolConfig;
+use crate::error::ComposerError;
+use crate::logger::capture_logs;
+use crate::types::{deserialize_ledger_version, Network};
+
+/// Body of `POST /api/call_function`, as sent by the web app. The arguments are strings or, as in
+/// `--request-json`, any JSON value.
+#[derive(Deserialize, Debug)]
+pub struct CallFunctionBody {
+    pub func: String,
+    pub type_args: Option<Vec<String>>,
+    pub args: Option<Vec<Value>>,
+    /// A number or a string, as the web app sends the text of its input.
+    #[serde(default, deserialize_with = "deserialize_ledger_version")]
+    pub ledger_version: Option<u64>,
+    pub network: Option<String>,
+    pub options: Option<CallFunctionOptions>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct CallFunctionOptions {
+    #[serde(default)]
+    pub with_logs: bool,
+}
+
+pub struct ServerOptions {
+    pub host: String,
+    pub port: u16,
+    pub static_dir: Option<String>,
+    pub rate_limit: u32,
+}
+
+/// Keeps one `Composer` per network, so the requests share the modules it has parsed.
+struct ServerState {
+    config: ToolConfig,
+    default_network: Network,
+    composers: RwLock<HashMap<Network, Arc<Composer>>>,
+}
+
+impl ServerState {
+    fn new(config: ToolConfig, default_network: Network) -> Self {
+        Self {
+            config,
+            default_network,
+            composers: RwLock::new(HashMap::new()),
+        }
+    }
+
+    // The composers do blocking IO, so this is only called from blocking tasks
+    fn get_composer(&self, network: &Network) -> Result<Arc<Composer>, ComposerError> {
+        if let Some(composer) = self.composers.read().get(network) {
+            return Ok(composer.clone());
+        }
+        let composer = Arc::new(Composer::new(network.clone(), &self.config)?);
+        Ok(self
+            .composers
+            .write()
+            .entry(network.clone())
+            .or_insert(composer)
+            .clone())
+    }
+
+    fn call_function(&self, body: CallFunctionBody) -> Result<Value, ComposerError> {
+        let network = match &body.network {
+            Some(network) if !network.trim().is_empty() => {
+                Network::from_str(network).map_err(ComposerError::InvalidInput)?
+            }
+            _ => self.default_network.clone(),
+        };
+        let composer = self.get_composer(&network)?;
+        let type_args = body
+            .type_args
+            .unwrap_or_default()
+            .into_iter()
+            .map(|arg| arg.trim().to_string())
+            .collect();
+        let args = body
+            .args
+            .unwrap_or_default()
+            .into_iter()
+            .map(|arg| match arg {
+                Value::String(arg) => Value::String(arg.trim().to_string()),
+                arg => arg,
+            })
+            .collect();
+        let execution_result = composer.call_view_json(
+            body.func.trim(),
+            type_args,
+            args,
+            body.ledger_version.unwrap_or_default(),
+        )?;
+        Ok(json!({
+            "ledger_version": execution_result.ledger_version,
+            "chain_id": execution_result.chain_id,
+            "network": execution_result.network,
+            "block_timestamp_usecs": execution_result.block_timestamp_usecs,
+            "return_values": execution_result.return_values,
+            "gas_used": execution_result.gas_used,
+            "timings": execution_result.timings,
+        }))
+    }
+}
+
+#[handler]
+async fn call_function(
+    Json(body): Json<CallFunctionBody>,
+    state: Data<&Arc<ServerState>>,
+) -> Json<Value> {
+    debug!("{:?}", body);
+    let state = state.0.clone();
+    let with_logs = body
+        .options
+        .as_ref()
+        .map_or(false, |options| options.with_logs);
+    // The call runs on one blocking thread, whose logs are the logs of the call
+    let (res, logs) = tokio::task::spawn_blocking(move || {
+        if with_logs {
+            let (res, logs) = capture_logs(|| state.call_function(body));
+            (res, Some(logs))
+        } else {
+            (state.call_function(body), None)
+        }
+    })
+    .await
+    .unwrap_or_else(|e| (Err(ComposerError::Internal(e.to_string())), None));
+    let (mut details, error) = match res {
+        Ok(details) => (details, false),
+        Err(err) => {
+            error!("{}", err);
+            (json!(err), true)
+        }
+    };
+    if let Some(logs) = logs {
+        details["logs"] = json!(logs);
+    }
+    Json(json!({
+        "details": details,
+        "error": error,
+    }))
+}
+
+#[handler]
+async fn health() -> &'static str {
+    "ok"
+}
+
+/// Ready once the node of the default network answers.
+#[handler]
+async fn ready(state: Data<&Arc<ServerState>>) -> Response {
+    let state = state.0.clone();
+    let res = tokio::task::spawn_blocking(move || {
+        state
+            .get_composer(&state.default_network)?
+            .latest_ledger_version()
+    })
+    .await;
+    match res {
+        Ok(Ok(ledger_version)) => Json(json!({ "ledger_version": ledger_version })).into_response(),
+        Ok(Err(err)) => (StatusCode::SERVICE_UNAVAILABLE, Json(json!(err))).into_response(),
+        Err(e) => (StatusCode::SERVICE_UNAVAILABLE, e.to_string()).into_response(),
+    }
+}
+
+/// Fixed window rate limiter keyed by the client IP address.
+struct RateLimiter {
+    max_requests: u32,
+    window: Duration,
+    counters: Mutex<HashMap<IpAddr, (Instant, u32)>>,
+}
+
+impl RateLimiter {
+    fn allow(&self, ip: IpAddr) -> bool {
+        let now = Instant::now();
+        let mut counters = self.counters.lock();
+        counters.retain(|_, (start, _)| now.duration_since(*start) < self.window);
+        let (_, count) = counters.entry(ip).or_insert((now, 0));
+        *count += 1;
+        *count <= self.max_requests
+    }
+}
+
+fn routes(state: Arc<ServerState>, rate_limit: u32, static_dir: Option<String>) -> impl Endpoint {
+    let rate_limiter = Arc::new(RateLimiter {
+        max_requests: rate_limit,
+        window: Duration::from_secs(60),
+        counters: Mutex::new(HashMap::new()),
+    });
+    let api = post(call_function).around(move |ep, req: Request| {
+        let rate_limiter = rate_limiter.clone();
+        async move {
+            let ip = req.remote_addr().as_socket_addr().map(|addr| addr.ip());
+            if let Some(ip) = ip {
+                if rate_limiter.max_requests > 0 && !rate_limiter.allow(ip) {
+                    return Ok::<_, poem::Error>(
+                        Response::builder()
+                            .status(StatusCode::TOO_MANY_REQUESTS)
+                            .body(format!(
+                                "You exceeded {} requests in 1 minute limit!",
+                                rate_limiter.max_requests
+                            )),
+                    );
+                }
+            }
+            ep.call(req).await.map(IntoResponse::into_response)
+        }
+    });
+    let mut app = Route::new()
+        .at("/api/call_function", api)
+        .at("/healthz", get(health))
+        .at("/readyz", get(ready));
+    if let Some(static_dir) = static_dir {
+        app = app.nest(
+            "/",
+            StaticFilesEndpoint::new(static_dir).index_file("index.html"),
+        );
+    }
+    app.with(Cors::new()).data(state)
+}
+
+pub async fn serve(
+    config: ToolConfig,
+    default_network: Network,
+    options: ServerOptions,
+) -> std::io::Result<()> {
+    let state = Arc::new(ServerState::new(config, default_network));
+    let app = routes(state, options.rate_limit, options.static_dir);
+    let addr = format!("{}:{}", options.host, options.port);
+    info!("The server is listening on {}", addr);
+    poem::Server::new(TcpListener::bind(addr)).run(app).await
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::composer::Composer;
+    use crate::config::ToolConfig;
+    use crate::fixture::FixtureStore;
+    use crate::server::{routes, RateLimiter, ServerState};
+    use crate::state_provider::{InMemoryStateProvider, StateProvider};
+    use crate::test_utils::module_bytecode;
+    use crate::types::Network;
+    use move_binary_format::file_format::{Bytecode, SignatureToken};
+    use move_core_types::identifier::Identifier;
+    use move_core_types::language_storage::{ModuleId, CORE_CODE_ADDRESS};
+    use parking_lot::Mutex;
+    use poem::http::StatusCode;
+    use poem::test::TestClient;
+    use serde_json::{json, Value};
+    use std::collections::HashMap;
+    use std::future::Future;
+    use std::net::IpAddr;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    fn server_state(provider: Arc<dyn StateProvider>) -> Arc<ServerState> {
+        let state = ServerState::new(ToolConfig::default(), Network::Localnet);
+        state.composers.write().insert(
+            Network::Localnet,
+            Arc::new(Composer::with_state_provider(
+                Network::Localnet,
+                provider,
+                None,
+            )),
+        );
+        Arc::new(state)
+    }
+
+    fn in_memory_state() -> Arc<dyn StateProvider> {
+        let mut state = InMemoryStateProvider::new(4, 10);
+        state.add_module(
+            ModuleId::new(CORE_CODE_ADDRESS, Identifier::new("numbers").unwrap()),
+            module_bytecode(
+                "numbers",
+                vec![(
+                    "one",
+                    vec![],
+                    vec![SignatureToken::U64],
+                    vec![Bytecode::LdU64(1), Bytecode::Ret],
+                )],
+            ),
+        );
+        state.add_module(
+            ModuleId::new(CORE_CODE_ADDRESS, Identifier::new("bytes").unwrap()),
+            module_bytecode(
+                "bytes",
+                vec![(
+                    "echo",
+                    vec![SignatureToken::Vector(Box::new(SignatureToken::U8))],
+                    vec![SignatureToken::Vector(Box::new(SignatureToken::U8))],
+                    vec![Bytecode::MoveLoc(0), Bytecode::Ret],
+                )],
+            ),
+        );
+        Arc::new(state)
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        tokio::runtime::Runtime::new().unwrap().block_on(future)
+    }
+
+    async fn call_function(client: &TestClient<impl poem::Endpoint>, body: Value) -> Value {
+        let resp = client
+            .post("/api/call_function")
+            .body_json(&body)
+            .send()
+            .await;
+        resp.assert_status_is_ok();
+        resp.0.into_body().into_json().await.unwrap()
+    }
+
+    #[test]
+    fn test_call_function() {
+        let client = TestClient::new(routes(server_state(in_memory_state()), 0, None));
+        block_on(async {
+            let res = call_function(&client, json!({ "func": "0x1::numbers::one" })).await;
+            assert_eq!(res["error"], json!(false));
+            assert_eq!(res["details"]["return_values"], json!(["1"]));
+            assert_eq!(res["details"]["ledger_version"], json!(10));
+            assert!(res["details"].get("logs").is_none());
+
+            // The web app sends the ledger version as the text of its input
+            for ledger_version in ["10", ""] {
+                let res = call_function(
+                    &client,
+                    json!({ "func": "0x1::numbers::one", "ledger_version": ledger_version }),
+                )
+                .await;
+                assert_eq!(res["error"], json!(false));
+                assert_eq!(res["details"]["ledger_version"], json!(10));
+            }
+
+            let res = call_function(
+                &client,
+                json!({ "func": "0x1::numbers::two", "options": { "with_logs": true } }),
+            )
+            .await;
+            assert_eq!(res["error"], json!(true));
+            assert_eq!(res["details"]["code"], json!("FUNCTION_NOT_FOUND"));
+            let logs = res["details"]["logs"].as_array().unwrap();
+            assert!(logs.iter().any(|line| line
+                .as_str()
+                .unwrap()
+                .contains("Pin the latest ledger version to 10")));
+        });
+    }
+
+    #[test]
+    fn test_refuse_file_arguments() {
+        let path = std::env::temp_dir().join(format!("bytes_{}", Uuid::new_v4()));
+        std::fs::write(&path, [1, 2]).unwrap();
+        let client = TestClient::new(routes(server_state(in_memory_state()), 0, None));
+        block_on(async {
+            let res = call_function(
+                &client,
+                json!({ "func": "0x1::bytes::echo", "args": ["0x0102"] }),
+            )
+            .await;
+            assert_eq!(res["error"], json!(false));
+            assert_eq!(res["details"]["return_values"], json!(["0x0102"]));
+
+            let res = call_function(
+                &client,
+                json!({ "func": "0x1::bytes::echo", "args": [format!("@{}", path.display())] }),
+            )
+            .await;
+            assert_eq!(res["error"], json!(true));
+            assert_eq!(res["details"]["code"], json!("INVALID_INPUT"));
+        });
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_readiness() {
+        let client = TestClient::new(routes(server_state(in_memory_state()), 0, None));
+        block_on(async {
+            let resp = client.get("/readyz").send().await;
+            resp.assert_status_is_ok();
+            let res: Value = resp.0.into_body().into_json().await.unwrap();
+            assert_eq!(res, json!({ "ledger_version": 10 }));
+        });
+
+        // A replay of an empty fixture has no ledger version to serve
+        let path = std::env::temp_dir().join(format!("fixture_{}.json", Uuid::new_v4()));
+        FixtureStore::record(&path).unwrap().save().unwrap();
+        let fixture = FixtureStore::replay(&path).unwrap();
+        let client = TestClient::new(routes(server_state(Arc::new(fixture)), 0, None));
+        block_on(async {
+            let resp = client.get("/readyz").send().await;
+            resp.assert_status(StatusCode::SERVICE_UNAVAILABLE);
+        });
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let rate_limiter = RateLimiter {
+            max_requests: 2,
+            window: Duration::from_millis(200),
+            counters: Mutex::new(HashMap::new()),
+        };
+        let ip = |ip: &str| ip.parse::<IpAddr>().unwrap();
+        assert!(rate_limiter.allow(ip("10.0.0.1")));
+        assert!(rate_limiter.allow(ip("10.0.0.1")));
+        assert!(!rate_limiter.allow(ip("10.0.0.1")));
+        // Each address has its own counter
+        assert!(rate_limiter.allow(ip("10.0.0.2")));
+        // A new window starts over
+        std::thread::sleep(Duration::from_millis(250));
+        assert!(rate_limiter.allow(ip("10.0.0.1")));
+    }
+}
@@ -0,0 +1,614 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use aptos_sdk::rest_client::aptos_api_types::mime_types::{BCS, JSON};
+use aptos_sdk::rest_client::aptos_api_types::U64;
+use aptos_sdk::rest_client::MoveModuleBytecode;
+use log::debug;
+use move_core_types::account_address::AccountAddress;
+use move_core_types::language_storage::{ModuleId, StructTag};
+use move_table_extension::TableHandle;
+use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
+use reqwest::blocking::Response;
+use reqwest::header::ACCEPT;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use url::Url;
+
+use crate::config::ToolConfig;
+use crate::error::ComposerError;
+use crate::helper::{get_node_url, get_rest_url, get_table_url};
+use crate::types::Network;
+
+/// Source of the chain state read by an execution. The module resolver and the VM storage only
+/// talk to this trait, so an execution can run against a node, a fixture file or a hand-built
+/// state alike.
+///
+/// All the reads return `None` when the item doesn't exist at `ledger_version`.
+pub trait StateProvider: Send + Sync {
+    /// Current ledger version of the source.
+    fn latest_version(&self) -> Result<u64, ComposerError>;
+
+    /// Chain id of the network the state belongs to.
+    fn chain_id(&self) -> Result<u8, ComposerError>;
+
+    fn get_module(
+        &self,
+        module_id: &ModuleId,
+        ledger_version: u64,
+    ) -> Result<Option<Vec<u8>>, ComposerError>;
+
+    fn get_resource(
+        &self,
+        address: &AccountAddress,
+        tag: &StructTag,
+        ledger_version: u64,
+    ) -> Result<Option<Vec<u8>>, ComposerError>;
+
+    fn get_table_item(
+        &self,
+        handle: &TableHandle,
+        key: &[u8],
+        ledger_version: u64,
+    ) -> Result<Option<Vec<u8>>, ComposerError>;
+
+    /// Bytecode of all the modules under `address`, to prefetch them at once. Sources which
+    /// can't list an account return nothing, the modules are then read one by one.
+    fn get_account_modules(
+        &self,
+        _address: &AccountAddress,
+        _ledger_version: u64,
+    ) -> Result<Vec<Vec<u8>>, ComposerError> {
+        Ok(vec![])
+    }
+}
+
+const X_APTOS_CURSOR: &str = "x-aptos-cursor";
+
+/// Part of the index of the REST API, `GET /v1`.
+#[derive(Deserialize)]
+struct IndexResponse {
+    chain_id: u8,
+    ledger_version: U64,
+}
+
+/// Reads the chain state from the REST API of a fullnode, one item per request. Table items are
+/// read from the table endpoint of the network when it has one.
+pub struct RestStateProvider {
+    node_url: Url,
+    table_url: Url,
+    http_client: reqwest::blocking::Client,
+    // The chain id never changes, it's kept from the first ledger info read
+    chain_id: OnceCell<u8>,
+}
+
+impl RestStateProvider {
+    pub fn new(node_url: Url) -> Self {
+        Self {
+            table_url: node_url.clone(),
+            node_url,
+            http_client: reqwest::blocking::Client::new(),
+            chain_id: OnceCell::new(),
+        }
+    }
+
+    /// Reads from the endpoints configured for `network`.
+    pub fn from_config(network: &Network, config: &ToolConfig) -> Result<Self, ComposerError> {
+        Ok(Self {
+            table_url: get_table_url(network, config)?,
+            ..Self::new(get_node_url(network, config)?)
+        })
+    }
+}
+
+impl RestStateProvider {
+    fn get_ledger_information(&self) -> Result<IndexResponse, ComposerError> {
+        let state = self
+            .http_client
+            .get(get_rest_url(&self.node_url, ""))
+            .send()?
+            .error_for_status()?
+            .json::<IndexResponse>()?;
+        let _ = self.chain_id.set(state.chain_id);
+        Ok(state)
+    }
+
+    /// Follows the `X-Aptos-Cursor` of a listing until its last page, a node only returns one
+    /// page of items per request. A missing account lists nothing, a page going missing midway
+    /// is an error.
+    fn get_pages<T>(
+        &self,
+        path: &str,
+        ledger_version: u64,
+        accept: &str,
+        parse: impl Fn(Response) -> Result<Vec<T>, ComposerError>,
+    ) -> Result<Vec<T>, ComposerError> {
+        collect_pages(|cursor| {
+            let mut url = get_rest_url(&self.node_url, path);
+            url.query_pairs_mut()
+                .append_pair("ledger_version", ledger_version.to_string().as_str());
+            if let Some(cursor) = cursor {
+                url.query_pairs_mut().append_pair("start", cursor);
+            }
+            let resp = self.http_client.get(url).header(ACCEPT, accept).send()?;
+            if resp.status() == StatusCode::NOT_FOUND {
+                return Ok(None);
+            }
+            let resp = resp.error_for_status()?;
+            let cursor = resp
+                .headers()
+                .get(X_APTOS_CURSOR)
+                .and_then(|value| value.to_str().ok())
+                .map(String::from);
+            Ok(Some((parse(resp)?, cursor)))
+        })
+    }
+
+    fn get_bcs(&self, url: Url) -> Result<Option<Vec<u8>>, ComposerError> {
+        let resp = self.http_client.get(url).header(ACCEPT, BCS).send()?;
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let bytes = resp.error_for_status()?.bytes()?;
+        Ok(Some(bytes.to_vec()))
+    }
+}
+
+/// The items of a page and the cursor of the next one, `None` for a missing page.
+type Page<T> = Option<(Vec<T>, Option<String>)>;
+
+/// Collects the items of a listing, starting without a cursor and passing the cursor of each page
+/// to `get_page` until a page comes without one. A missing first page is an empty listing, while
+/// a missing later page fails it rather than returning part of the items.
+fn collect_pages<T>(
+    mut get_page: impl FnMut(Option<&str>) -> Result<Page<T>, ComposerError>,
+) -> Result<Vec<T>, ComposerError> {
+    let mut items = vec![];
+    let mut cursor: Option<String> = None;
+    loop {
+        match get_page(cursor.as_deref())? {
+            Some((page, next)) => {
+                items.extend(page);
+                cursor = next;
+            }
+            None => {
+                return match cursor {
+                    None => Ok(items),
+                    Some(cursor) => Err(ComposerError::Network(format!(
+                        "The page at cursor `{}` is not found",
+                        cursor
+                    ))),
+                }
+            }
+        }
+        if cursor.is_none() {
+            return Ok(items);
+        }
+    }
+}
+
+impl StateProvider for RestStateProvider {
+    fn latest_version(&self) -> Result<u64, ComposerError> {
+        Ok(self.get_ledger_information()?.ledger_version.0)
+    }
+
+    fn chain_id(&self) -> Result<u8, ComposerError> {
+        if let Some(chain_id) = self.chain_id.get() {
+            return Ok(*chain_id);
+        }
+        Ok(self.get_ledger_information()?.chain_id)
+    }
+
+    fn get_module(
+        &self,
+        module_id: &ModuleId,
+        ledger_version: u64,
+    ) -> Result<Option<Vec<u8>>, ComposerError> {
+        let mut url = get_rest_url(
+            &self.node_url,
+            format!(
+                "accounts/{}/module/{}",
+                module_id.address().to_hex_literal(),
+                module_id.name()
+            )
+            .as_str(),
+        );
+        url.query_pairs_mut()
+            .append_pair("ledger_version", ledger_version.to_string().as_str());
+        let resp = self.http_client.get(url).send()?;
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let module = resp.error_for_status()?.json::<MoveModuleBytecode>()?;
+        debug!("load module: {}", module_id);
+        Ok(Some(module.bytecode.0))
+    }
+
+    fn get_resource(
+        &self,
+        address: &AccountAddress,
+        tag: &StructTag,
+        ledger_version: u64,
+    ) -> Result<Option<Vec<u8>>, ComposerError> {
+        let mut url = get_rest_url(
+            &self.node_url,
+            format!("accounts/{}/resource/{}", address.to_hex_literal(), tag).as_str(),
+        );
+        url.query_pairs_mut()
+            .append_pair("ledger_version", ledger_version.to_string().as_str());
+        debug!("load resource {} from address {}", tag, address);
+        self.get_bcs(url)
+    }
+
+    fn get_table_item(
+        &self,
+        handle: &TableHandle,
+        key: &[u8],
+        ledger_version: u64,
+    ) -> Result<Option<Vec<u8>>, ComposerError> {
+        let mut url = get_rest_url(
+            &self.table_url,
+            format!("tables/{}/raw_item", handle.0.to_hex_literal()).as_str(),
+        );
+        url.query_pairs_mut()
+            .append_pair("ledger_version", ledger_version.to_string().as_str());
+        let mut map = HashMap::new();
+        map.insert("key", hex::encode(key));
+        let resp = self
+            .http_client
+            .post(url)
+            .header(ACCEPT, BCS)
+            .json(&map)
+            .send()?;
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let bytes = resp.error_for_status()?.bytes()?;
+        Ok(Some(bytes.to_vec()))
+    }
+
+    fn get_account_modules(
+        &self,
+        address: &AccountAddress,
+        ledger_version: u64,
+    ) -> Result<Vec<Vec<u8>>, ComposerError> {
+        let path = format!("accounts/{}/modules", address.to_hex_literal());
+        let modules = self.get_pages(path.as_str(), ledger_version, JSON, |resp| {
+            Ok(resp.json::<Vec<MoveModuleBytecode>>()?)
+        })?;
+        debug!("load {} modules from address {}", modules.len(), address);
+        Ok(modules
+            .into_iter()
+            .map(|module| module.bytecode.0)
+            .collect())
+    }
+}
+
+/// Memoizes the resources read from another provider, the missing ones included, keyed by
+/// address, struct tag and ledger version. It's meant to live for one execution, as it's never
+/// evicted.
+pub struct CachingStateProvider {
+    inner: Arc<dyn StateProvider>,
+    resources: Mutex<HashMap<(AccountAddress, StructTag, u64), Option<Vec<u8>>>>,
+}
+
+impl CachingStateProvider {
+    pub fn new(inner: Arc<dyn StateProvider>) -> Self {
+        Self {
+            inner,
+            resources: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl StateProvider for CachingStateProvider {
+    fn latest_version(&self) -> Result<u64, ComposerError> {
+        self.inner.latest_version()
+    }
+
+    fn chain_id(&self) -> Result<u8, ComposerError> {
+        self.inner.chain_id()
+    }
+
+    fn get_module(
+        &self,
+        module_id: &ModuleId,
+        ledger_version: u64,
+    ) -> Result<Option<Vec<u8>>, ComposerError> {
+        self.inner.get_module(module_id, ledger_version)
+    }
+
+    fn get_resource(
+        &self,
+        address: &AccountAddress,
+        tag: &StructTag,
+        ledger_version: u64,
+    ) -> Result<Option<Vec<u8>>, ComposerError> {
+        let key = (*address, tag.clone(), ledger_version);
+        if let Some(resource) = self.resources.lock().get(&key) {
+            return Ok(resource.clone());
+        }
+        // Errors are not cached, the next read tries again
+        let resource = self.inner.get_resource(address, tag, ledger_version)?;
+        self.resources.lock().insert(key, resource.clone());
+        Ok(resource)
+    }
+
+    fn get_table_item(
+        &self,
+        handle: &TableHandle,
+        key: &[u8],
+        ledger_version: u64,
+    ) -> Result<Option<Vec<u8>>, ComposerError> {
+        self.inner.get_table_item(handle, key, ledger_version)
+    }
+
+    fn get_account_modules(
+        &self,
+        address: &AccountAddress,
+        ledger_version: u64,
+    ) -> Result<Vec<Vec<u8>>, ComposerError> {
+        self.inner.get_account_modules(address, ledger_version)
+    }
+}
+
+/// Chain state held in memory, e.g. to run functions against a hand-built state in tests. The
+/// same state is served at every ledger version.
+#[derive(Default)]
+pub struct InMemoryStateProvider {
+    chain_id: u8,
+    latest_version: u64,
+    modules: HashMap<ModuleId, Vec<u8>>,
+    resources: HashMap<(AccountAddress, StructTag), Vec<u8>>,
+    table_items: HashMap<(AccountAddress, Vec<u8>), Vec<u8>>,
+}
+
+impl InMemoryStateProvider {
+    pub fn new(chain_id: u8, latest_version: u64) -> Self {
+        Self {
+            chain_id,
+            latest_version,
+            ..Default::default()
+        }
+    }
+
+    pub fn add_module(&mut self, module_id: ModuleId, bytecode: Vec<u8>) {
+        self.modules.insert(module_id, bytecode);
+    }
+
+    pub fn add_resource(&mut self, address: AccountAddress, tag: StructTag, bytes: Vec<u8>) {
+        self.resources.insert((address, tag), bytes);
+    }
+
+    pub fn add_table_item(&mut self, handle: TableHandle, key: Vec<u8>, bytes: Vec<u8>) {
+        self.table_items.insert((handle.0, key), bytes);
+    }
+}
+
+impl StateProvider for InMemoryStateProvider {
+    fn latest_version(&self) -> Result<u64, ComposerError> {
+        Ok(self.latest_version)
+    }
+
+    fn chain_id(&self) -> Result<u8, ComposerError> {
+        Ok(self.chain_id)
+    }
+
+    fn get_module(
+        &self,
+        module_id: &ModuleId,
+        _ledger_version: u64,
+    ) -> Result<Option<Vec<u8>>, ComposerError> {
+        Ok(self.modules.get(module_id).cloned())
+    }
+
+    fn get_resource(
+        &self,
+        address: &AccountAddress,
+        tag: &StructTag,
+        _ledger_version: u64,
+    ) -> Result<Option<Vec<u8>>, ComposerError> {
+        Ok(self.resources.get(&(*address, tag.clone())).cloned())
+    }
+
+    fn get_table_item(
+        &self,
+        handle: &TableHandle,
+        key: &[u8],
+        _ledger_version: u64,
+    ) -> Result<Option<Vec<u8>>, ComposerError> {
+        Ok(self.table_items.get(&(handle.0, key.to_vec())).cloned())
+    }
+
+    fn get_account_modules(
+        &self,
+        address: &AccountAddress,
+        _ledger_version: u64,
+    ) -> Result<Vec<Vec<u8>>, ComposerError> {
+        Ok(self
+            .modules
+            .iter()
+            .filter(|(module_id, _)| module_id.address() == address)
+            .map(|(_, bytecode)| bytecode.clone())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::ComposerError;
+    use crate::module_resolver::CacheModuleResolver;
+    use crate::state_provider::{
+        collect_pages, CachingStateProvider, InMemoryStateProvider, StateProvider,
+    };
+    use crate::storage::InMemoryLazyStorage;
+    use crate::types::Network;
+    use move_core_types::account_address::AccountAddress;
+    use move_core_types::identifier::Identifier;
+    use move_core_types::language_storage::{ModuleId, StructTag, CORE_CODE_ADDRESS};
+    use move_core_types::resolver::{ModuleResolver, ResourceResolver};
+    use move_table_extension::{TableHandle, TableResolver};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Counts the resource reads reaching the state.
+    struct CountingStateProvider {
+        state: InMemoryStateProvider,
+        resource_reads: AtomicUsize,
+    }
+
+    impl StateProvider for CountingStateProvider {
+        fn latest_version(&self) -> Result<u64, ComposerError> {
+            self.state.latest_version()
+        }
+
+        fn chain_id(&self) -> Result<u8, ComposerError> {
+            self.state.chain_id()
+        }
+
+        fn get_module(
+            &self,
+            module_id: &ModuleId,
+            ledger_version: u64,
+        ) -> Result<Option<Vec<u8>>, ComposerError> {
+            self.state.get_module(module_id, ledger_version)
+        }
+
+        fn get_resource(
+            &self,
+            address: &AccountAddress,
+            tag: &StructTag,
+            ledger_version: u64,
+        ) -> Result<Option<Vec<u8>>, ComposerError> {
+            self.resource_reads.fetch_add(1, Ordering::SeqCst);
+            self.state.get_resource(address, tag, ledger_version)
+        }
+
+        fn get_table_item(
+            &self,
+            handle: &TableHandle,
+            key: &[u8],
+            ledger_version: u64,
+        ) -> Result<Option<Vec<u8>>, ComposerError> {
+            self.state.get_table_item(handle, key, ledger_version)
+        }
+    }
+
+    #[test]
+    fn test_storage_reads_from_in_memory_state() {
+        let tag = StructTag {
+            address: CORE_CODE_ADDRESS,
+            module: Identifier::new("block").unwrap(),
+            name: Identifier::new("BlockResource").unwrap(),
+            type_params: vec![],
+        };
+        let handle = TableHandle(AccountAddress::from_hex_literal("0x2").unwrap());
+        let mut state = InMemoryStateProvider::new(4, 10);
+        state.add_resource(CORE_CODE_ADDRESS, tag.clone(), vec![1, 2]);
+        state.add_table_item(handle, vec![3], vec![4]);
+        let provider = Arc::new(state);
+
+        let module_resolver =
+            CacheModuleResolver::new(Network::Mainnet, provider.clone(), None, false);
+        let storage = InMemoryLazyStorage::new(10, provider, module_resolver);
+        assert_eq!(
+            storage.get_resource(&CORE_CODE_ADDRESS, &tag).unwrap(),
+            Some(vec![1, 2])
+        );
+        assert_eq!(
+            storage.resolve_table_entry(&handle, &[3]).unwrap(),
+            Some(vec![4])
+        );
+        assert_eq!(storage.resolve_table_entry(&handle, &[5]).unwrap(), None);
+        let module_id = ModuleId::new(CORE_CODE_ADDRESS, Identifier::new("coin").unwrap());
+        assert_eq!(storage.get_module(&module_id).unwrap(), None);
+    }
+
+    #[test]
+    fn test_caching_state_provider() {
+        let tag = |name: &str| StructTag {
+            address: CORE_CODE_ADDRESS,
+            module: Identifier::new("block").unwrap(),
+            name: Identifier::new(name).unwrap(),
+            type_params: vec![],
+        };
+        let mut state = InMemoryStateProvider::new(4, 10);
+        state.add_resource(CORE_CODE_ADDRESS, tag("BlockResource"), vec![1, 2]);
+        let counting = Arc::new(CountingStateProvider {
+            state,
+            resource_reads: AtomicUsize::new(0),
+        });
+        let provider = CachingStateProvider::new(counting.clone());
+
+        for _ in 0..2 {
+            assert_eq!(
+                provider
+                    .get_resource(&CORE_CODE_ADDRESS, &tag("BlockResource"), 10)
+                    .unwrap(),
+                Some(vec![1, 2])
+            );
+            assert_eq!(
+                provider
+                    .get_resource(&CORE_CODE_ADDRESS, &tag("Missing"), 10)
+                    .unwrap(),
+                None
+            );
+        }
+        assert_eq!(counting.resource_reads.load(Ordering::SeqCst), 2);
+        // Another version is another read
+        provider
+            .get_resource(&CORE_CODE_ADDRESS, &tag("BlockResource"), 11)
+            .unwrap();
+        assert_eq!(counting.resource_reads.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_collect_pages() {
+        // The pages by cursor, the second one is empty but not the last
+        let pages = |cursor: Option<&str>| match cursor {
+            None => Some((vec![1, 2], Some(String::from("a")))),
+            Some("a") => Some((vec![], Some(String::from("b")))),
+            Some("b") => Some((vec![3], None)),
+            _ => None,
+        };
+        let mut cursors = vec![];
+        let items = collect_pages(|cursor| {
+            cursors.push(cursor.map(String::from));
+            Ok(pages(cursor))
+        })
+        .unwrap();
+        assert_eq!(items, vec![1, 2, 3]);
+        assert_eq!(
+            cursors,
+            vec![None, Some(String::from("a")), Some(String::from("b"))]
+        );
+
+        // A missing account lists nothing, a single page needs no cursor
+        assert!(collect_pages::<u8>(|_| Ok(None)).unwrap().is_empty());
+        assert!(collect_pages::<u8>(|_| Ok(Some((vec![], None))))
+            .unwrap()
+            .is_empty());
+        let items = collect_pages(|cursor| {
+            assert!(cursor.is_none());
+            Ok(Some((vec![1], None)))
+        })
+        .unwrap();
+        assert_eq!(items, vec![1]);
+
+        // A failed page fails the listing
+        let res = collect_pages::<u8>(|cursor| match cursor {
+            None => Ok(Some((vec![1], Some(String::from("a"))))),
+            Some(_) => Err(ComposerError::Network(String::from("timeout"))),
+        });
+        assert!(matches!(res, Err(ComposerError::Network(_))));
+
+        // A page missing after the first one fails the listing instead of truncating it
+        let res = collect_pages::<u8>(|cursor| match cursor {
+            None => Ok(Some((vec![1], Some(String::from("a"))))),
+            Some(_) => Ok(None),
+        });
+        assert!(matches!(res, Err(ComposerError::Network(message)) if message.contains("`a`")));
+    }
+}
@@ -1,30 +1,24 @@
-use aptos_sdk::move_types::account_address::AccountAddress as AptosAccountAddress;
-use aptos_sdk::move_types::language_storage::StructTag as AptosStructTag;
-
+use crate::error::ComposerError;
 use crate::module_resolver::CacheModuleResolver;
-use crate::types::Network;
+use crate::resource_group::{get_group_member, get_resource_group};
+use crate::state_provider::StateProvider;
+use crate::types::ExecutionTimings;
 use anyhow::{bail, Error, Result};
-use aptos_sdk::rest_client::aptos_api_types::mime_types::BCS;
-use aptos_sdk::rest_client::Client;
-use log::{debug, error};
+use log::error;
 use move_core_types::account_address::AccountAddress;
 use move_core_types::effects::{AccountChangeSet, ChangeSet, Op};
 use move_core_types::identifier::Identifier;
-use move_core_types::language_storage::{ModuleId, StructTag, TypeTag};
+use move_core_types::language_storage::{ModuleId, StructTag};
 use move_core_types::resolver::{ModuleResolver, ResourceResolver};
 use move_table_extension::{TableHandle, TableResolver};
-use reqwest::header::ACCEPT;
-use reqwest::StatusCode;
-use std::borrow::BorrowMut;
-use std::cell::Cell;
+use std::cell::RefCell;
 use std::collections::HashMap;
-use std::str::FromStr;
-use std::sync::RwLock;
+use std::sync::Arc;
+use std::time::Instant;
 use std::{
     collections::{btree_map, BTreeMap},
     fmt::Debug,
 };
-use tokio::runtime::Runtime;
 
 /// Simple in-memory storage for modules and resources under an account.
 #[derive(Debug, Clone)]
@@ -85,14 +79,18 @@ impl InMemoryAccountStorage {
     }
 }
 
-/// Simple in-memory lazy storage that can be used as a Move VM storage backend. It restores resources from the Aptos chain
+/// Simple in-memory lazy storage that can be used as a Move VM storage backend. It restores resources from a `StateProvider`
 // #[derive(Clone)]
 pub struct InMemoryLazyStorage {
     accounts: BTreeMap<AccountAddress, InMemoryAccountStorage>,
     ledger_version: u64,
-    network: Network,
-    client: Client,
+    provider: Arc<dyn StateProvider>,
     module_resolver: CacheModuleResolver,
+    // The VM only reports a generic storage error, so the first failure is kept to be surfaced
+    error: RefCell<Option<ComposerError>>,
+    timings: RefCell<ExecutionTimings>,
+    // Resource group of each struct read so far, keyed by (address, module, name)
+    resource_groups: RefCell<HashMap<(AccountAddress, Identifier, Identifier), Option<StructTag>>>,
 }
 
 impl InMemoryLazyStorage {
@@ -115,75 +113,109 @@ impl InMemoryLazyStorage {
 
     pub fn new(
         ledger_version: u64,
-        network: Network,
-        client: Client,
+        provider: Arc<dyn StateProvider>,
         module_resolver: CacheModuleResolver,
     ) -> Self {
         Self {
             accounts: BTreeMap::new(),
             ledger_version,
-            network,
-            client,
+            provider,
             module_resolver,
+            error: RefCell::new(None),
+            timings: RefCell::new(ExecutionTimings::default()),
+            resource_groups: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Time spent reading modules, resources and table items so far.
+    pub fn timings(&self) -> ExecutionTimings {
+        self.timings.borrow().clone()
+    }
+
+    /// Takes the first error raised while reading the chain state, if any.
+    pub fn take_error(&self) -> Option<ComposerError> {
+        self.error.borrow_mut().take()
+    }
+
+    fn record_error<T>(&self, res: Result<T, ComposerError>) -> Result<T, ComposerError> {
+        if let Err(err) = &res {
+            error!("{}", err);
+            self.error.borrow_mut().get_or_insert_with(|| err.clone());
+        }
+        res
+    }
+
+    /// Resource group the struct belongs to, as declared in the metadata of its module.
+    fn get_resource_group(&self, tag: &StructTag) -> Result<Option<StructTag>, ComposerError> {
+        let key = (tag.address, tag.module.clone(), tag.name.clone());
+        if let Some(group) = self.resource_groups.borrow().get(&key) {
+            return Ok(group.clone());
         }
+        let module_id = ModuleId::new(tag.address, tag.module.clone());
+        let (bytecode, _) = self.timed(
+            |timings| &mut timings.module_fetch_us,
+            || {
+                self.module_resolver
+                    .get_module(&module_id, self.ledger_version)
+            },
+        )?;
+        let group = bytecode.and_then(|bytecode| get_resource_group(&bytecode, tag.name.as_str()));
+        self.resource_groups.borrow_mut().insert(key, group.clone());
+        Ok(group)
+    }
+
+    fn timed<T>(&self, field: fn(&mut ExecutionTimings) -> &mut u64, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let res = f();
+        *field(&mut self.timings.borrow_mut()) += start.elapsed().as_micros() as u64;
+        res
     }
 }
 
 impl ModuleResolver for InMemoryLazyStorage {
-    type Error = ();
+    type Error = ComposerError;
 
     fn get_module(&self, module_id: &ModuleId) -> Result<Option<Vec<u8>>, Self::Error> {
-        let (mod_, _) = self.module_resolver.get_module(module_id).unwrap();
+        let (mod_, _) = self.record_error(self.timed(
+            |timings| &mut timings.module_fetch_us,
+            || {
+                self.module_resolver
+                    .get_module(module_id, self.ledger_version)
+            },
+        ))?;
 
         Ok(mod_)
     }
 }
 
 impl ResourceResolver for InMemoryLazyStorage {
-    type Error = ();
+    type Error = ComposerError;
 
     fn get_resource(
         &self,
         address: &AccountAddress,
         tag: &StructTag,
     ) -> Result<Option<Vec<u8>>, Self::Error> {
-        // Get account's resources from the chain
-        let rest_client = self.client.clone();
-        let aptos_account = AptosAccountAddress::from_bytes(address.into_bytes());
-        match aptos_account {
-            Ok(account_address) => {
-                let matched_resource;
-                if self.ledger_version > 0 {
-                    matched_resource = Runtime::new()
-                        .unwrap()
-                        .block_on(rest_client.get_account_resources_at_version_bcs(
-                            account_address,
-                            self.ledger_version,
-                        ))
-                        .unwrap()
-                        .into_inner();
-                } else {
-                    matched_resource = Runtime::new()
-                        .unwrap()
-                        .block_on(rest_client.get_account_resources_bcs(account_address))
-                        .unwrap()
-                        .into_inner();
-                }
-                if let Some(resource) = matched_resource
-                    .get(&AptosStructTag::from_str(tag.to_string().as_str()).unwrap())
-                {
-                    debug!(
-                        "load resource from address {} to get {}",
-                        address.to_string(),
-                        tag.to_string()
-                    );
-                    return Ok(Option::from(resource.clone()));
-                }
-                return Ok(None);
-            }
-            Err(err) => error!("{}", err),
-        }
-        Ok(None)
+        let res = self.get_resource_group(tag).and_then(|group| match group {
+            // Members of a resource group are stored together under the group tag
+            Some(group) => self
+                .timed(
+                    |timings| &mut timings.resource_fetch_us,
+                    || {
+                        self.provider
+                            .get_resource(address, &group, self.ledger_version)
+                    },
+                )?
+                .map_or(Ok(None), |bytes| get_group_member(&bytes, tag)),
+            None => self.timed(
+                |timings| &mut timings.resource_fetch_us,
+                || {
+                    self.provider
+                        .get_resource(address, tag, self.ledger_version)
+                },
+            ),
+        });
+        self.record_error(res)
     }
 }
 
@@ -193,31 +225,12 @@ impl TableResolver for InMemoryLazyStorage {
         handle: &TableHandle,
         key: &[u8],
     ) -> std::result::Result<Option<Vec<u8>>, Error> {
-        let url_string = if self.ledger_version > 0 {
-            format!(
-                "https://fullnode.{}.aptoslabs.com/v1/tables/0x{}/raw_item?ledger_version={}",
-                self.network, handle.0, self.ledger_version
-            )
-        } else {
-            format!(
-                "https://fullnode.{}.aptoslabs.com/v1/tables/0x{}/raw_item",
-                self.network, handle.0
-            )
-        };
-        let c = reqwest::blocking::Client::new();
-        let mut map = HashMap::new();
-        map.insert("key", hex::encode(key));
-        let resp = c
-            .post(url_string)
-            .header(ACCEPT, BCS)
-            .json(&map)
-            .send()
-            .unwrap();
-        if resp.status() == StatusCode::NOT_FOUND {
-            return Ok(None);
-        }
-
-        let bytes = resp.bytes().unwrap();
-        Ok(Some(bytes.to_vec()))
+        Ok(self.record_error(self.timed(
+            |timings| &mut timings.table_fetch_us,
+            || {
+                self.provider
+                    .get_table_item(handle, key, self.ledger_version)
+            },
+        ))?)
     }
 }
@@ -0,0 +1,171 @@
+use move_binary_format::file_format::{
+    empty_module, AbilitySet, Bytecode, CodeUnit, CompiledModule, Constant, ConstantPoolIndex,
+    FieldDefinition, FieldHandle, FieldHandleIndex, FunctionDefinition, FunctionHandle,
+    FunctionHandleIndex, IdentifierIndex, ModuleHandleIndex, Signature, SignatureIndex,
+    SignatureToken, StructDefinition, StructDefinitionIndex, StructFieldInformation, StructHandle,
+    StructHandleIndex, TypeSignature, Visibility,
+};
+use move_core_types::account_address::AccountAddress;
+use move_core_types::identifier::Identifier;
+use move_core_types::language_storage::CORE_CODE_ADDRESS;
+use move_core_types::metadata::Metadata;
+
+/// A public function of a test module: its name, parameter types, return types and code.
+pub type TestFunction = (
+    &'static str,
+    Vec<SignatureToken>,
+    Vec<SignatureToken>,
+    Vec<Bytecode>,
+);
+
+/// Builds the bytecode of the module `0x1::<name>`, so the tests can run functions against an
+/// `InMemoryStateProvider`.
+pub fn module_bytecode(name: &str, functions: Vec<TestFunction>) -> Vec<u8> {
+    let mut module = TestModule::new(name);
+    for (name, params, returns, code) in functions {
+        module.add_function(name, vec![], (params, returns), Some((vec![], code)));
+    }
+    module.bytecode()
+}
+
+/// Builds a module `0x1::<name>` with structs, constants and native functions, for the tests
+/// which need more than `module_bytecode`.
+pub struct TestModule {
+    module: CompiledModule,
+}
+
+impl TestModule {
+    pub fn new(name: &str) -> Self {
+        let mut module = empty_module();
+        module.identifiers[0] = Identifier::new(name).unwrap();
+        module.address_identifiers[0] = CORE_CODE_ADDRESS;
+        Self { module }
+    }
+
+    /// Adds a struct without type parameters. Its handle has the same index as its definition.
+    pub fn add_struct(
+        &mut self,
+        name: &str,
+        abilities: AbilitySet,
+        fields: Vec<(&str, SignatureToken)>,
+    ) -> StructDefinitionIndex {
+        let name = self.identifier(name);
+        self.module.struct_handles.push(StructHandle {
+            module: ModuleHandleIndex(0),
+            name,
+            abilities,
+            type_parameters: vec![],
+        });
+        let fields = fields
+            .into_iter()
+            .map(|(name, signature)| FieldDefinition {
+                name: self.identifier(name),
+                signature: TypeSignature(signature),
+            })
+            .collect();
+        self.module.struct_defs.push(StructDefinition {
+            struct_handle: StructHandleIndex(self.module.struct_handles.len() as u16 - 1),
+            field_information: StructFieldInformation::Declared(fields),
+        });
+        StructDefinitionIndex(self.module.struct_defs.len() as u16 - 1)
+    }
+
+    /// The handle of the field at `index` of a struct, for the field borrows.
+    pub fn field_handle(&mut self, owner: StructDefinitionIndex, index: u16) -> FieldHandleIndex {
+        let handle = FieldHandle {
+            owner,
+            field: index,
+        };
+        let index = position_or_push(&mut self.module.field_handles, handle);
+        FieldHandleIndex(index as u16)
+    }
+
+    pub fn add_address_constant(&mut self, address: AccountAddress) -> ConstantPoolIndex {
+        let constant = Constant {
+            type_: SignatureToken::Address,
+            data: address.to_vec(),
+        };
+        ConstantPoolIndex(position_or_push(&mut self.module.constant_pool, constant) as u16)
+    }
+
+    /// Adds a public function taking `signature` (the parameter and return types). `code` is the
+    /// types of the locals after the parameters and the instructions, `None` for a native
+    /// function. The resources the code borrows are acquired.
+    pub fn add_function(
+        &mut self,
+        name: &str,
+        type_parameters: Vec<AbilitySet>,
+        signature: (Vec<SignatureToken>, Vec<SignatureToken>),
+        code: Option<(Vec<SignatureToken>, Vec<Bytecode>)>,
+    ) -> FunctionHandleIndex {
+        let name = self.identifier(name);
+        let handle = FunctionHandle {
+            module: ModuleHandleIndex(0),
+            name,
+            parameters: self.signature(signature.0),
+            return_: self.signature(signature.1),
+            type_parameters,
+        };
+        self.module.function_handles.push(handle);
+        let function = FunctionHandleIndex(self.module.function_handles.len() as u16 - 1);
+        let mut acquires_global_resources = vec![];
+        let code = code.map(|(locals, code)| {
+            for instruction in &code {
+                if let Bytecode::ImmBorrowGlobal(index)
+                | Bytecode::MutBorrowGlobal(index)
+                | Bytecode::MoveFrom(index) = instruction
+                {
+                    if !acquires_global_resources.contains(index) {
+                        acquires_global_resources.push(*index);
+                    }
+                }
+            }
+            CodeUnit {
+                locals: self.signature(locals),
+                code,
+            }
+        });
+        self.module.function_defs.push(FunctionDefinition {
+            function,
+            visibility: Visibility::Public,
+            is_entry: false,
+            acquires_global_resources,
+            code,
+        });
+        function
+    }
+
+    /// Attaches the metadata `value` under `key`, as the Aptos compiler attaches its attributes.
+    pub fn add_metadata(&mut self, key: &[u8], value: Vec<u8>) {
+        self.module.metadata.push(Metadata {
+            key: key.to_vec(),
+            value,
+        });
+    }
+
+    pub fn bytecode(&self) -> Vec<u8> {
+        let mut bytecode = vec![];
+        self.module.serialize(&mut bytecode).unwrap();
+        bytecode
+    }
+
+    fn identifier(&mut self, name: &str) -> IdentifierIndex {
+        let identifier = Identifier::new(name).unwrap();
+        IdentifierIndex(position_or_push(&mut self.module.identifiers, identifier) as u16)
+    }
+
+    fn signature(&mut self, tokens: Vec<SignatureToken>) -> SignatureIndex {
+        SignatureIndex(position_or_push(&mut self.module.signatures, Signature(tokens)) as u16)
+    }
+}
+
+// The verifier refuses duplicated identifiers, signatures, constants and field handles
+fn position_or_push<T: PartialEq>(items: &mut Vec<T>, item: T) -> usize {
+    match items.iter().position(|existing| *existing == item) {
+        Some(index) => index,
+        None => {
+            items.push(item);
+            items.len() - 1
+        }
+    }
+}
@@ -1,30 +1,129 @@
-use clap::{command, Parser, ValueEnum};
-use move_core_types::value::MoveValue;
+use crate::error::ComposerError;
+use clap::{command, Parser, Subcommand, ValueEnum};
 use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::Value;
 use std::fmt::{Display, Formatter};
+use std::str::FromStr;
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Default)]
 pub struct ExecutionResult {
-    pub(crate) log_path: String,
-    pub(crate) return_values: Vec<Value>,
+    pub log_path: String,
+    pub ledger_version: u64,
+    pub chain_id: Option<u8>,
+    pub network: String,
+    /// Timestamp of the block at the ledger version, in microseconds.
+    pub block_timestamp_usecs: Option<u64>,
+    pub return_values: Vec<Value>,
+    /// Gas units consumed by the call, `None` when it's unmetered.
+    pub gas_used: Option<u64>,
+    pub timings: ExecutionTimings,
+    pub error: Option<ComposerError>,
 }
 
-#[derive(ValueEnum, Eq, PartialEq, Hash, Clone, Copy, Debug)]
+/// A call read by `--request-json`, in the shape of the body of the Aptos `POST /v1/view` with
+/// the ledger version and the network added. The arguments are encoded after the parameter types
+/// of the function.
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct ViewRequest {
+    pub function: String,
+    #[serde(default)]
+    pub type_arguments: Vec<String>,
+    #[serde(default)]
+    pub arguments: Vec<Value>,
+    /// A number or a string, as the Aptos API writes the 64-bit integers.
+    #[serde(default, deserialize_with = "deserialize_ledger_version")]
+    pub ledger_version: Option<u64>,
+    pub network: Option<Network>,
+}
+
+/// Reads a ledger version written as a number or a string, a blank string being the latest one.
+pub fn deserialize_ledger_version<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<Value>::deserialize(deserializer)? {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::Number(n)) => n
+            .as_u64()
+            .map(Some)
+            .ok_or_else(|| serde::de::Error::custom(format!("invalid ledger version {}", n))),
+        Some(Value::String(s)) if s.trim().is_empty() => Ok(None),
+        Some(Value::String(s)) => s
+            .trim()
+            .parse::<u64>()
+            .map(Some)
+            .map_err(|_| serde::de::Error::custom(format!("invalid ledger version `{}`", s))),
+        Some(value) => Err(serde::de::Error::custom(format!(
+            "invalid ledger version {}",
+            value
+        ))),
+    }
+}
+
+/// Gas budget of a call, in gas units of the Aptos gas schedule.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GasBudget {
+    /// The maximum gas units of a transaction in the gas schedule.
+    #[default]
+    Default,
+    Limit(u64),
+    /// Executes without metering, no gas usage is reported.
+    Unmetered,
+}
+
+/// Time spent in each step of an execution, in microseconds. The VM execution doesn't include
+/// the reads of modules, resources and table items done while executing.
+#[derive(Serialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct ExecutionTimings {
+    pub module_fetch_us: u64,
+    pub resource_fetch_us: u64,
+    pub table_fetch_us: u64,
+    pub vm_execution_us: u64,
+    pub conversion_us: u64,
+    pub total_us: u64,
+}
+
+/// Network to run against. Besides the public Aptos networks and a local testnet, any network
+/// named in `network_configs` of the config file can be used.
+#[derive(Eq, PartialEq, Hash, Clone, Debug)]
 pub enum Network {
     Mainnet,
     Testnet,
     Devnet,
+    /// A local testnet, e.g. started by `aptos node run-local-testnet`.
+    Localnet,
+    /// Any other network, by its lowercase name.
+    Named(String),
 }
 
 impl Display for Network {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let str = match self {
-            Network::Mainnet => "mainnet",
-            Network::Testnet => "testnet",
-            Network::Devnet => "devnet",
-        };
-        write!(f, "{}", str)
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for Network {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().to_lowercase();
+        match name.as_str() {
+            "mainnet" => Ok(Network::Mainnet),
+            "testnet" => Ok(Network::Testnet),
+            "devnet" => Ok(Network::Devnet),
+            "localnet" => Ok(Network::Localnet),
+            _ if !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') =>
+            {
+                Ok(Network::Named(name))
+            }
+            _ => Err(format!(
+                "invalid network name `{}`, expect letters, digits, `-` or `_`",
+                s
+            )),
+        }
     }
 }
 
@@ -34,12 +133,7 @@ impl<'de> Deserialize<'de> for Network {
         D: Deserializer<'de>,
     {
         let s = String::deserialize(deserializer)?;
-        Ok(match s.to_lowercase().as_str() {
-            "testnet" => Network::Testnet,
-            "mainnet" => Network::Mainnet,
-            "devnet" => Network::Devnet,
-            _ => panic!("unknown network: {}", s),
-        })
+        Network::from_str(s.as_str()).map_err(serde::de::Error::custom)
     }
 }
 
@@ -49,6 +143,8 @@ impl Network {
             Network::Mainnet => "mainnet",
             Network::Testnet => "testnet",
             Network::Devnet => "devnet",
+            Network::Localnet => "localnet",
+            Network::Named(name) => name.as_str(),
         }
     }
 }
@@ -85,41 +181,131 @@ impl Display for LogLevel {
 
 /// Call the view function on Aptos blockchain
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, subcommand_negates_reqs = true)]
 pub struct ViewFunction {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// Function name as `<ADDRESS>::<MODULE_ID>::<FUNCTION_NAME>`
     ///
     /// Example: `0x1::block::get_current_block_height`
-    #[clap(short, long)]
-    pub(crate) function_id: String,
+    #[clap(short, long, required_unless_present = "request_json")]
+    pub function_id: Option<String>,
 
     /// Arguments separated by spaces.
     ///
-    /// Supported types [u8, u64, u128, bool, hex, string, address, raw]
+    /// Supported types [u8, u64, u128, bool, address, vector, struct]. Vectors are a JSON array
+    /// or comma separated, nested vectors a JSON array. Structs are a JSON object of their fields,
+    /// except `String`, `Option`, `Object` and `FixedPoint32` given by their value.
     ///
-    /// Example: `0x1 true 0`
-    #[clap(short, long, num_args = 0..)]
-    pub(crate) args: Option<Vec<String>>,
+    /// Example: `0x1 true 0 1,2,3 [["0x1"],["0x2"]]`
+    #[clap(short, long, num_args = 0.., conflicts_with = "request_json")]
+    pub args: Option<Vec<String>>,
 
     /// TypeTag arguments separated by spaces.
     ///
     /// Example: `u8 u64 u128 bool address vector signer`
-    #[clap(short, long, num_args = 0..)]
-    pub(crate) type_args: Option<Vec<String>>,
+    #[clap(short, long, num_args = 0.., conflicts_with = "request_json")]
+    pub type_args: Option<Vec<String>>,
+
+    /// Read the call from a JSON file, or from stdin with `-`, in the shape of the Aptos `/v1/view`
+    /// body: `{"function", "type_arguments", "arguments"}`, plus the optional `ledger_version` and
+    /// `network` which replace `--ledger-version` and `--network`.
+    ///
+    /// The arguments are JSON values, e.g. `[true, "0x1", [1, 2], {"x": 1}]`.
+    #[clap(long, value_name = "FILE", conflicts_with = "function_id")]
+    pub request_json: Option<String>,
 
     /// Ledger version, if not apply or 0, use the latest ledger version.
+    ///
+    /// The latest version is read once at startup, and all the reads of the execution use it.
     #[clap(short, long, default_value_t = 0)]
-    pub(crate) ledger_version: u64,
+    pub ledger_version: u64,
+
+    /// Gas budget of the call in gas units, the maximum gas units of a transaction by default.
+    #[clap(long, value_name = "GAS_UNITS", conflicts_with = "unmetered")]
+    pub gas_budget: Option<u64>,
 
-    /// Network to use.
-    #[clap(short, long, default_value_t = Network::Mainnet)]
-    pub(crate) network: Network,
+    /// Execute without gas metering.
+    #[clap(long)]
+    pub unmetered: bool,
+
+    /// Download all the modules under the address of the function before the call, instead of one
+    /// by one as the execution needs them.
+    #[clap(long)]
+    pub prefetch_modules: bool,
+
+    /// Record the chain state read by the call into a fixture file, extending it if it exists.
+    #[clap(long, value_name = "FILE", conflicts_with = "replay")]
+    pub record: Option<String>,
+
+    /// Replay the call from a fixture file written by `--record`, without any network access.
+    #[clap(long, value_name = "FILE")]
+    pub replay: Option<String>,
+
+    /// Network to use: mainnet, testnet, devnet, localnet or any network of the config file.
+    #[clap(short, long, global = true, default_value_t = Network::Mainnet)]
+    pub network: Network,
 
     /// Config file to use.
-    #[clap(short, long)]
-    pub(crate) config: Option<String>,
+    #[clap(short, long, global = true)]
+    pub config: Option<String>,
 
     /// Log level.
-    #[clap(long, default_value_t = LogLevel::Off)]
-    pub(crate) log_level: LogLevel,
+    #[clap(long, global = true, default_value_t = LogLevel::Off)]
+    pub log_level: LogLevel,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Start an HTTP server calling view functions, with the module caches kept across requests.
+    ///
+    /// `--network` is the network used when a request doesn't specify one.
+    Serve {
+        /// Address to listen on.
+        #[clap(long, default_value = "0.0.0.0")]
+        host: String,
+
+        /// Port to listen on.
+        #[clap(long, default_value_t = 4000)]
+        port: u16,
+
+        /// Folder of static files served at `/`, e.g. the built web app.
+        #[clap(long)]
+        static_dir: Option<String>,
+
+        /// Maximum requests per minute from one IP address, 0 to disable the limit.
+        #[clap(long, default_value_t = 50)]
+        rate_limit: u32,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::{Network, ViewRequest};
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_view_request() {
+        let request: ViewRequest = serde_json::from_str(
+            r#"{"function": "0x1::coin::balance", "type_arguments": ["0x1::aptos_coin::AptosCoin"], "arguments": ["0x1"], "ledger_version": "35842267"}"#,
+        )
+        .unwrap();
+        assert_eq!(request.type_arguments, vec!["0x1::aptos_coin::AptosCoin"]);
+        assert_eq!(request.arguments, vec![json!("0x1")]);
+        assert_eq!(request.ledger_version, Some(35842267));
+        assert_eq!(request.network, None);
+
+        let request: ViewRequest = serde_json::from_str(
+            r#"{"function": "0x1::block::get_current_block_height", "ledger_version": 10, "network": "Testnet"}"#,
+        )
+        .unwrap();
+        assert!(request.arguments.is_empty());
+        assert_eq!(request.ledger_version, Some(10));
+        assert_eq!(request.network, Some(Network::Testnet));
+        assert!(
+            serde_json::from_str::<ViewRequest>(r#"{"function": "", "ledger_version": -1}"#)
+                .is_err()
+        );
+    }
 }